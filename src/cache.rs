@@ -0,0 +1,192 @@
+use conf::Conf;
+use error::{Result, ResultContext};
+use files;
+use post::Post;
+use serde_json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// FNV-1a, which unlike the hasher of the standard library
+// gives the same results across builds of izzet.
+#[derive(Debug, Clone, Copy)]
+pub struct Hasher(u64);
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Hasher::default()
+    }
+
+    pub fn write(mut self, bytes: &[u8]) -> Self {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+
+    pub fn write_u64(self, n: u64) -> Self {
+        self.write(&n.to_le_bytes())
+    }
+
+    pub fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+// Hash of the configuration, leaving out options that only
+// control how the build runs rather than what it produces.
+pub fn hash_conf(conf: &Conf) -> Result<u64> {
+    let mut conf = conf.clone();
+    conf.force = None;
+    conf.full = None;
+    conf.prune = None;
+    Ok(Hasher::new().write(serde_json::to_string(&conf)?.as_bytes()).finish())
+}
+
+// Hash of the names and contents of all files under `dir`.
+pub fn hash_dir<P: AsRef<Path>>(dir: P) -> Result<u64> {
+    let mut h = Hasher::new();
+    if dir.as_ref().is_dir() {
+        for p in files::walk(&dir)? {
+            h = h.write(p.to_string_lossy().as_bytes()).write(&files::fread(&p)?);
+        }
+    }
+    Ok(h.finish())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Source {
+    pub hash: u64,
+    // `None` if the source is not a post at all
+    pub post: Option<Post>,
+}
+
+// What the last build read and wrote, saved in the site directory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Cache {
+    pub version: String,
+    pub out_dir: PathBuf,
+    // source path -> hash of its content and the parsed post
+    pub sources: BTreeMap<String, Source>,
+    // output path relative to the output directory -> hash of its inputs
+    pub outputs: BTreeMap<String, u64>,
+    // every file written or copied to the output directory by this build or
+    // an earlier one and not removed since, by its path relative to it
+    #[serde(default)]
+    pub written: BTreeSet<String>,
+}
+
+impl Cache {
+    // A missing or unreadable cache just means building everything.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        files::fread(&path).ok()
+            .and_then(|data| serde_json::from_slice::<Cache>(&data).ok())
+            .filter(|c| c.version == env!("CARGO_PKG_VERSION"))
+            .unwrap_or_default()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let data = serde_json::to_vec(self)
+            .context("error saving build cache".to_string())?;
+        files::fwrite(path, &data, true)
+    }
+}
+
+// Writes the outputs of a build, skipping those whose inputs
+// are the same as in the last build and still exist. It can be
+// shared by the threads rendering the site.
+pub struct Writer<'a> {
+    out_dir: &'a Path,
+    force: bool,
+    last: Option<&'a BTreeMap<String, u64>>,
+    outputs: Mutex<BTreeMap<String, u64>>,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(out_dir: &'a Path, force: bool, last: Option<&'a Cache>) -> Self {
+        Writer {
+            out_dir,
+            force,
+            last: last.filter(|c| c.out_dir == out_dir).map(|c| &c.outputs),
+            outputs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn outputs(self) -> BTreeMap<String, u64> {
+        self.outputs.into_inner().unwrap()
+    }
+
+    pub fn write<F>(&self, url: &str, key: u64, render: F) -> Result<()>
+            where F: FnOnce() -> Result<String>
+    {
+        // skip the leading slash to make the output path correct
+        let url = url.trim_start_matches('/');
+        self.outputs.lock().unwrap().insert(url.to_string(), key);
+
+        let path = self.out_dir.join(url);
+        if self.last.and_then(|l| l.get(url)) == Some(&key) && path.exists() {
+            return Ok(());
+        }
+        files::fwrite(path, render()?.as_bytes(), self.force)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::{env, fs};
+
+    #[test]
+    fn test_writer() {
+        let dir = env::temp_dir().join("writer");
+        let mut last = Cache::default();
+        last.out_dir = dir.clone();
+        last.outputs.insert("a.html".to_string(), 1);
+        last.outputs.insert("b.html".to_string(), 1);
+        files::fwrite(dir.join("a.html"), b"old", true).unwrap();
+        files::fwrite(dir.join("b.html"), b"old", true).unwrap();
+
+        let w = Writer::new(&dir, true, Some(&last));
+        w.write("/a.html", 1, || Ok("new".to_string())).unwrap();
+        w.write("/b.html", 2, || Ok("new".to_string())).unwrap();
+        w.write("/c.html", 1, || Ok("new".to_string())).unwrap();
+
+        assert!(files::fread(dir.join("a.html")).unwrap() == b"old");
+        assert!(files::fread(dir.join("b.html")).unwrap() == b"new");
+        assert!(files::fread(dir.join("c.html")).unwrap() == b"new");
+        assert!(w.outputs().len() == 3);
+
+        // the cache of another output directory is ignored
+        let other = Cache::default();
+        let w = Writer::new(&dir, true, Some(&other));
+        w.write("/a.html", 1, || Ok("new".to_string())).unwrap();
+        assert!(files::fread(dir.join("a.html")).unwrap() == b"new");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_save() {
+        let path = env::temp_dir().join("cache.test");
+        let mut c = Cache::default();
+        c.version = env!("CARGO_PKG_VERSION").to_string();
+        c.sources.insert("a.md".to_string(), Source { hash: 42, post: Some(Post::new()) });
+        c.save(&path).unwrap();
+
+        let c = Cache::load(&path);
+        assert!(c.sources["a.md"].hash == 42);
+        assert!(c.sources["a.md"].post.is_some());
+
+        fs::write(&path, b"garbage").unwrap();
+        assert!(Cache::load(&path).sources.is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+}
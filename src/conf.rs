@@ -1,25 +1,58 @@
 use error::{Error, Result};
+use feed::FeedConf;
 use files;
+use highlight::HighlightConf;
+use markdown::MarkdownConf;
+use nav::NavConf;
+use post::FrontMatter;
+use prune::PruneConf;
+use sitemap::SitemapConf;
 use std::path::Path;
 use toml;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Conf {
     pub force: Option<bool>,
+    pub drafts: Option<bool>,
+    pub full: Option<bool>,
     pub in_dir: Option<String>,
     pub out_dir: Option<String>,
     pub port: Option<u16>,
     pub title: Option<String>,
+    pub paginate: Option<usize>,
+    pub archive_paginate: Option<usize>,
+    pub base_url: Option<String>,
+    pub front_matter: Option<FrontMatter>,
+    pub theme: Option<String>,
+    pub feed: Option<FeedConf>,
+    pub sitemap: Option<SitemapConf>,
+    pub highlight: Option<HighlightConf>,
+    pub markdown: Option<MarkdownConf>,
+    pub nav: Option<NavConf>,
+    pub prune: Option<PruneConf>,
 }
 
 impl Default for Conf {
     fn default() -> Self {
         Conf {
             force: None,
+            drafts: None,
+            full: None,
             in_dir: None,
             out_dir: None,
             port: None,
             title: None,
+            paginate: None,
+            archive_paginate: None,
+            base_url: None,
+            front_matter: None,
+            theme: None,
+            feed: None,
+            sitemap: None,
+            highlight: None,
+            markdown: None,
+            nav: None,
+            prune: None,
         }
     }
 }
@@ -42,10 +75,23 @@ mod tests {
     fn test_conf_defeault_value() {
         let c = Conf::default();
         assert!(c.force == None);
+        assert!(c.drafts == None);
+        assert!(c.full == None);
         assert!(c.in_dir == None);
         assert!(c.out_dir == None);
         assert!(c.port == None);
         assert!(c.title == None);
+        assert!(c.paginate == None);
+        assert!(c.archive_paginate == None);
+        assert!(c.base_url == None);
+        assert!(c.front_matter.is_none());
+        assert!(c.theme == None);
+        assert!(c.feed.is_none());
+        assert!(c.sitemap.is_none());
+        assert!(c.highlight.is_none());
+        assert!(c.markdown.is_none());
+        assert!(c.nav.is_none());
+        assert!(c.prune.is_none());
     }
 
     #[test]
@@ -55,7 +101,9 @@ mod tests {
         f.write(b"force = true\n\
                   in_dir = \".\"\n\
                   port = 9999\n\
-                  title = \"title\"").unwrap();
+                  title = \"title\"\n\
+                  [feed]\n\
+                  limit = 5").unwrap();
 
         let c = Conf::from_file(&p).unwrap();
         assert!(c.force == Some(true));
@@ -63,6 +111,7 @@ mod tests {
         assert!(c.out_dir == None);
         assert!(c.port == Some(9999));
         assert!(c.title == Some("title".to_string()));
+        assert!(c.feed.unwrap().limit() == 5);
 
         fs::remove_file(p).unwrap();
     }
@@ -0,0 +1,100 @@
+use csv;
+use error::{Error, Result};
+use files;
+use serde_json::{self, Map, Value};
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml;
+
+// Rows of a CSV file as maps from the names in the header to the values.
+fn from_csv(data: &[u8]) -> Result<Value> {
+    let mut reader = csv::Reader::from_reader(data);
+    let headers = reader.headers().map_err(|e| Error::new(e.to_string()))?.clone();
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| Error::new(e.to_string()))?;
+        rows.push(Value::Object(headers.iter()
+            .zip(record.iter())
+            .map(|(h, v)| (h.to_string(), Value::String(v.to_string())))
+            .collect::<Map<_, _>>()));
+    }
+    Ok(Value::Array(rows))
+}
+
+fn parse(path: &Path, data: &[u8]) -> Option<Result<Value>> {
+    let ext = path.extension().and_then(|s| s.to_str())?;
+    let value = match ext {
+        "toml" => toml::from_slice(data).map_err(|e| e.to_string()),
+        "json" => serde_json::from_slice(data).map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml::from_slice(data).map_err(|e| e.to_string()),
+        "csv" => from_csv(data).map_err(|e| e.to_string()),
+        _ => return None,
+    };
+    Some(value.map_err(|e| Error::new(format!("error parsing data file {:?}: {}", path, e))))
+}
+
+// Load the data files right under `dir` by their names without the extension.
+// Files of other kinds are left alone.
+pub fn load<P: AsRef<Path>>(dir: P) -> Result<BTreeMap<String, Value>> {
+    let mut data = BTreeMap::new();
+    if !dir.as_ref().is_dir() {
+        return Ok(data);
+    }
+
+    let mut paths = fs::read_dir(&dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<::std::io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    for path in paths.into_iter().filter(|p| p.is_file()) {
+        let value = match parse(&path, &files::fread(&path)?) {
+            Some(v) => v?,
+            None => continue,
+        };
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        if data.insert(name.clone(), value).is_some() {
+            return Err(Error::new(format!("more than one data file named {:?} in {:?}",
+                                          name, dir.as_ref())));
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::env;
+
+    #[test]
+    fn test_load() {
+        let dir = env::temp_dir().join("data");
+        let write = |name: &str, data: &str| {
+            files::fwrite(dir.join(name), data.as_bytes(), true).unwrap()
+        };
+        write("menu.toml", "[[items]]\nname = \"Home\"\nurl = \"/\"\n");
+        write("projects.json", "[{\"name\": \"izzet\"}]");
+        write("people.yaml", "- name: Jing\n");
+        write("papers.csv", "title,year\nA,2017\nB,2018\n");
+        write("notes.txt", "not data");
+
+        let data = load(&dir).unwrap();
+        assert!(data.len() == 4);
+        assert!(data["menu"]["items"][0]["url"] == "/");
+        assert!(data["projects"][0]["name"] == "izzet");
+        assert!(data["people"][0]["name"] == "Jing");
+        assert!(data["papers"][1]["year"] == "2018");
+
+        write("broken.json", "[");
+        let err = load(&dir).unwrap_err().to_string();
+        assert!(err.contains("broken.json"));
+        fs::remove_file(dir.join("broken.json")).unwrap();
+
+        write("menu.json", "{}");
+        assert!(load(&dir).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+        assert!(load(env::temp_dir().join("no-data")).unwrap().is_empty());
+    }
+}
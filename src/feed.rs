@@ -0,0 +1,185 @@
+use chrono::Local;
+use conf::Conf;
+use error::{Error, Result};
+use post::Post;
+
+const DEFAULT_ATOM_PATH: &str = "atom.xml";
+const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FeedConf {
+    pub path: Option<String>,
+    pub rss_path: Option<String>,
+    pub limit: Option<usize>,
+    pub full_content: Option<bool>,
+    pub per_tag: Option<bool>,
+    // the site title if not given
+    pub author: Option<String>,
+}
+
+impl FeedConf {
+    pub fn path(&self) -> String {
+        self.path.clone().unwrap_or(DEFAULT_ATOM_PATH.to_string())
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT)
+    }
+}
+
+// A feed for one list of articles, which are assumed to be sorted
+// from the newest to the oldest as `Site` does.
+pub struct Feed<'a> {
+    title: String,
+    author: String,
+    // absolute URL of the page the feed is about and of the feed itself
+    home: String,
+    base_url: &'a str,
+    articles: Vec<&'a Post>,
+    full_content: bool,
+}
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+     .replace('"', "&quot;")
+     .replace('\'', "&apos;")
+}
+
+pub fn abs_url(base_url: &str, url: &str) -> String {
+    format!("{}/{}", base_url.trim_end_matches('/'), url.trim_start_matches('/'))
+}
+
+impl<'a> Feed<'a> {
+    pub fn new<I>(conf: &'a Conf, title: String, home: &str, articles: I) -> Result<Self>
+            where I: IntoIterator<Item = &'a Post>
+    {
+        let base_url = conf.base_url.as_ref()
+            .ok_or(Error::new("base_url is required to generate feeds".to_string()))?;
+        let feed = conf.feed.clone().unwrap_or_default();
+
+        Ok(Feed {
+            title,
+            author: feed.author.clone().or(conf.title.clone()).unwrap_or_default(),
+            home: abs_url(base_url, home),
+            base_url,
+            articles: articles.into_iter().take(feed.limit()).collect(),
+            full_content: feed.full_content.unwrap_or(true),
+        })
+    }
+
+    fn content(&self, p: &Post) -> String {
+        escape(if self.full_content { &p.content } else { &p.summary })
+    }
+
+    pub fn atom(&self, self_url: &str) -> Result<String> {
+        let updated = self.articles.first()
+            .map(|p| p.ts)
+            .unwrap_or(Local::now());
+
+        let mut xml = String::new();
+        xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+        xml += "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+        xml += &format!("  <title>{}</title>\n", escape(&self.title));
+        xml += &format!("  <id>{}</id>\n", escape(&self.home));
+        xml += &format!("  <link href=\"{}\"/>\n", escape(&self.home));
+        xml += &format!("  <link rel=\"self\" href=\"{}\"/>\n",
+                        escape(&abs_url(self.base_url, self_url)));
+        xml += &format!("  <updated>{}</updated>\n", updated.to_rfc3339());
+        // entries without an author of their own have that of the feed
+        xml += &format!("  <author>\n    <name>{}</name>\n  </author>\n", escape(&self.author));
+
+        for p in &self.articles {
+            let url = escape(&abs_url(self.base_url, &p.url()?));
+            let tag = if self.full_content { "content" } else { "summary" };
+            xml += "  <entry>\n";
+            xml += &format!("    <title>{}</title>\n", escape(&p.title));
+            xml += &format!("    <id>{}</id>\n", url);
+            xml += &format!("    <link href=\"{}\"/>\n", url);
+            xml += &format!("    <published>{}</published>\n", p.ts.to_rfc3339());
+            xml += &format!("    <updated>{}</updated>\n", p.ts.to_rfc3339());
+            for t in &p.tags {
+                xml += &format!("    <category term=\"{}\"/>\n", escape(t));
+            }
+            xml += &format!("    <{0} type=\"html\">{1}</{0}>\n", tag, self.content(p));
+            xml += "  </entry>\n";
+        }
+
+        xml += "</feed>\n";
+        Ok(xml)
+    }
+
+    pub fn rss(&self) -> Result<String> {
+        let mut xml = String::new();
+        xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+        xml += "<rss version=\"2.0\">\n";
+        xml += "  <channel>\n";
+        xml += &format!("    <title>{}</title>\n", escape(&self.title));
+        xml += &format!("    <link>{}</link>\n", escape(&self.home));
+        xml += &format!("    <description>{}</description>\n", escape(&self.title));
+        if let Some(p) = self.articles.first() {
+            xml += &format!("    <lastBuildDate>{}</lastBuildDate>\n", p.ts.to_rfc2822());
+        }
+
+        for p in &self.articles {
+            let url = escape(&abs_url(self.base_url, &p.url()?));
+            xml += "    <item>\n";
+            xml += &format!("      <title>{}</title>\n", escape(&p.title));
+            xml += &format!("      <link>{}</link>\n", url);
+            xml += &format!("      <guid>{}</guid>\n", url);
+            xml += &format!("      <pubDate>{}</pubDate>\n", p.ts.to_rfc2822());
+            for t in &p.tags {
+                xml += &format!("      <category>{}</category>\n", escape(t));
+            }
+            xml += &format!("      <description>{}</description>\n", self.content(p));
+            xml += "    </item>\n";
+        }
+
+        xml += "  </channel>\n";
+        xml += "</rss>\n";
+        Ok(xml)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atom() {
+        let mut c = Conf::default();
+        c.base_url = Some("https://example.com/".to_string());
+        c.feed = Some(FeedConf { limit: Some(1), full_content: Some(false),
+                                 ..FeedConf::default() });
+
+        let mut p = Post::new();
+        p.meta.title = "A & B".to_string();
+        p.meta.link = "ab".to_string();
+        p.meta.url = "/{{ link }}.html".to_string();
+        p.content = "<p>first</p><p>second</p>".to_string();
+        p.summary = "<p>first</p>".to_string();
+        let posts = vec![p, Post::new()];
+
+        let atom = Feed::new(&c, "t".to_string(), "/", &posts).unwrap()
+            .atom("/atom.xml").unwrap();
+        assert!(atom.contains("<title>A &amp; B</title>"));
+        assert!(atom.contains("<link href=\"https://example.com/ab.html\"/>"));
+        assert!(atom.contains("<link rel=\"self\" href=\"https://example.com/atom.xml\"/>"));
+        assert!(atom.contains("<summary type=\"html\">&lt;p&gt;first&lt;/p&gt;</summary>"));
+        assert!(atom.matches("<entry>").count() == 1);
+        assert!(atom.contains("<author>\n    <name></name>\n  </author>"));
+
+        c.title = Some("Site".to_string());
+        let atom = Feed::new(&c, "t".to_string(), "/", &posts).unwrap()
+            .atom("/atom.xml").unwrap();
+        assert!(atom.contains("<name>Site</name>"));
+        c.feed = Some(FeedConf { author: Some("Jing".to_string()), ..FeedConf::default() });
+        let atom = Feed::new(&c, "t".to_string(), "/", &posts).unwrap()
+            .atom("/atom.xml").unwrap();
+        assert!(atom.contains("<name>Jing</name>"));
+
+        c.base_url = None;
+        assert!(Feed::new(&c, "t".to_string(), "/", &posts).is_err());
+    }
+}
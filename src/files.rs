@@ -1,7 +1,8 @@
 use error::{Result, ResultContext};
-use std::fs::{create_dir_all, File, OpenOptions};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::fs::{create_dir_all, metadata, read_dir, File, OpenOptions};
+use std::io::{copy, Read, Write};
+use std::path::{Path, PathBuf};
 
 pub fn get_opener(force: bool) -> OpenOptions {
     let mut opener = OpenOptions::new();
@@ -34,3 +35,110 @@ pub fn fwrite<P: AsRef<Path>>(path: P, data: &[u8], force: bool) -> Result<()> {
         .context(format!("error writing {:?}", path.as_ref()))?;
     Ok(())
 }
+
+// All files under `dir` recursively, in a stable order.
+pub fn walk<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let mut entries = read_dir(&dir)
+        .context(format!("error reading {:?}", dir.as_ref()))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<::std::io::Result<Vec<_>>>()
+        .context(format!("error reading {:?}", dir.as_ref()))?;
+    entries.sort();
+
+    for p in entries {
+        if p.is_dir() {
+            paths.extend(walk(&p)?);
+        }
+        else {
+            paths.push(p);
+        }
+    }
+    Ok(paths)
+}
+
+// Whether `dst` is a copy of `src` that is not older than it.
+fn is_fresh_copy(src: &Path, dst: &Path) -> bool {
+    match (metadata(src), metadata(dst)) {
+        (Ok(s), Ok(d)) => match (s.modified(), d.modified()) {
+            (Ok(st), Ok(dt)) => s.len() == d.len() && dt >= st,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Mirror all files under `src` into `dst`, skipping those whose copy
+// is up to date. Other existing files are only overwritten with `force`.
+pub fn copy_tree<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q, force: bool) -> Result<()> {
+    copy_trees(&[src], dst, force)
+}
+
+// Mirror the files under each of `srcs` into `dst` like `copy_tree`,
+// where a file under one of them hides the same file under the later ones.
+pub fn copy_trees<P: AsRef<Path>, Q: AsRef<Path>>(srcs: &[P], dst: Q, force: bool) -> Result<()> {
+    let mut copied = BTreeSet::new();
+    for src in srcs {
+        for from in walk(src)? {
+            let rel = from.strip_prefix(src).unwrap().to_path_buf();
+            let to = dst.as_ref().join(&rel);
+            if !copied.insert(rel) || is_fresh_copy(&from, &to) {
+                continue;
+            }
+            if let Some(dir) = to.parent() {
+                if !dir.exists() {
+                    create_dir_all(dir).context(format!("error creating {:?}", dir))?;
+                }
+            }
+            File::open(&from)
+                .and_then(|mut f| get_opener(force).open(&to).and_then(|mut t| copy(&mut f, &mut t)))
+                .context(format!("error copying {:?} to {:?}", from, to))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::{env, fs};
+
+    #[test]
+    fn test_copy_tree() {
+        let src = env::temp_dir().join("copy_src");
+        let dst = env::temp_dir().join("copy_dst");
+        fwrite(src.join("css").join("a.css"), b"a", true).unwrap();
+
+        copy_tree(&src, &dst, false).unwrap();
+        assert!(fread(dst.join("css").join("a.css")).unwrap() == b"a");
+
+        // unchanged files are skipped even without force
+        copy_tree(&src, &dst, false).unwrap();
+
+        fwrite(src.join("css").join("a.css"), b"bb", true).unwrap();
+        assert!(copy_tree(&src, &dst, false).is_err());
+        copy_tree(&src, &dst, true).unwrap();
+        assert!(fread(dst.join("css").join("a.css")).unwrap() == b"bb");
+
+        fs::remove_dir_all(src).unwrap();
+        fs::remove_dir_all(dst).unwrap();
+    }
+
+    #[test]
+    fn test_copy_trees() {
+        let first = env::temp_dir().join("copy_first");
+        let second = env::temp_dir().join("copy_second");
+        let dst = env::temp_dir().join("copy_trees_dst");
+        fwrite(first.join("a.css"), b"first", true).unwrap();
+        fwrite(second.join("a.css"), b"second", true).unwrap();
+        fwrite(second.join("b.css"), b"b", true).unwrap();
+
+        copy_trees(&[&first, &second], &dst, false).unwrap();
+        assert!(fread(dst.join("a.css")).unwrap() == b"first");
+        assert!(fread(dst.join("b.css")).unwrap() == b"b");
+
+        for d in &[first, second, dst] {
+            fs::remove_dir_all(d).unwrap();
+        }
+    }
+}
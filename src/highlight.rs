@@ -0,0 +1,94 @@
+use error::{Error, Result};
+use std::sync::OnceLock;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{self, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+const DEFAULT_THEME: &str = "InspiredGitHub";
+const DEFAULT_STYLESHEET: &str = "highlight.css";
+// prefix of the CSS classes, so that they don't clash with those of the theme
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+// Loading these takes a while, so it's done once and only if needed.
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HighlightConf {
+    pub theme: Option<String>,
+    pub css: Option<bool>,
+    pub stylesheet: Option<String>,
+}
+
+impl HighlightConf {
+    pub fn theme(&self) -> Result<&'static Theme> {
+        let name = self.theme.as_deref().unwrap_or(DEFAULT_THEME);
+        THEMES.get_or_init(ThemeSet::load_defaults).themes.get(name)
+            .ok_or(Error::new(format!("unknown highlight theme {:?}", name)))
+    }
+
+    pub fn css(&self) -> bool {
+        self.css.unwrap_or(false)
+    }
+
+    pub fn stylesheet(&self) -> String {
+        self.stylesheet.clone().unwrap_or(DEFAULT_STYLESHEET.to_string())
+    }
+}
+
+// Highlight `code` written in the language of the fence info `info`,
+// e.g. `rust` or `rust,ignore`. `None` if the language is unknown.
+pub fn highlight(code: &str, info: &str, conf: &HighlightConf) -> Result<Option<String>> {
+    let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+    let lang = info.split(|c: char| c == ',' || c.is_whitespace()).next().unwrap_or("");
+    let syntax = match syntaxes.find_syntax_by_token(lang) {
+        Some(s) if !lang.is_empty() => s,
+        _ => return Ok(None),
+    };
+
+    if !conf.css() {
+        return html::highlighted_html_for_string(code, syntaxes, syntax, conf.theme()?)
+            .map(Some)
+            .map_err(|e| Error::new(format!("error highlighting {} code: {}", lang, e)));
+    }
+
+    let mut gen = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        gen.parse_html_for_line_which_includes_newline(line)
+            .map_err(|e| Error::new(format!("error highlighting {} code: {}", lang, e)))?;
+    }
+    Ok(Some(format!("<pre class=\"hl-code\"><code>{}</code></pre>\n", gen.finalize())))
+}
+
+// The stylesheet for the classes used when `css` is set.
+pub fn stylesheet(conf: &HighlightConf) -> Result<String> {
+    html::css_for_theme_with_class_style(conf.theme()?, CLASS_STYLE)
+        .map_err(|e| Error::new(format!("error generating highlight stylesheet: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let mut c = HighlightConf::default();
+        let code = "fn main() {}\n";
+
+        let html = highlight(code, "rust,ignore", &c).unwrap().unwrap();
+        assert!(html.starts_with("<pre style="));
+        assert!(html.contains("<span style="));
+        assert!(highlight(code, "no-such-lang", &c).unwrap().is_none());
+        assert!(highlight(code, "", &c).unwrap().is_none());
+
+        c.css = Some(true);
+        let html = highlight(code, "rust", &c).unwrap().unwrap();
+        assert!(html.starts_with("<pre class=\"hl-code\">"));
+        assert!(html.contains("<span class=\"hl-"));
+        assert!(stylesheet(&c).unwrap().contains(".hl-code"));
+
+        c.theme = Some("no-such-theme".to_string());
+        assert!(stylesheet(&c).is_err());
+    }
+}
@@ -1,28 +1,57 @@
 extern crate chrono;
+extern crate csv;
 extern crate pulldown_cmark;
+extern crate rayon;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
 #[macro_use]
 extern crate serde_derive;
+extern crate slug;
+extern crate syntect;
 extern crate tera;
 extern crate tiny_http;
 extern crate toml;
 
+pub mod cache;
 pub mod conf;
+pub mod data;
 pub mod error;
+pub mod feed;
 pub mod files;
+pub mod highlight;
 pub mod markdown;
+pub mod nav;
 pub mod new;
 pub mod post;
+pub mod prune;
+pub mod section;
 pub mod server;
 pub mod site;
+pub mod sitemap;
+pub mod taxonomy;
+pub mod template;
+pub mod theme;
+pub mod watch;
 
 pub const DEFAULT_PORT: u16 = 10950;
 
 pub const CONFIG_FILE:   &str = "izzet.toml";
+pub const THEME_CONFIG:  &str = "theme.toml";
+pub const CACHE_FILE:    &str = ".izzet-cache.json";
 // XXX this should be made configurable
 pub const NOJEKYLL_FILE: &str = ".nojekyll";
 pub const INDEX_FILE:    &str = "index.html";
 pub const POST_FILE:     &str = "post.html";
 pub const ARCHIVE_FILE:  &str = "archive.html";
+pub const SECTION_FILE:  &str = "section.html";
+pub const TAXONOMY_FILE: &str = "taxonomy.html";
+pub const TERM_FILE:     &str = "term.html";
+pub const ROBOTS_FILE:   &str = "robots.txt";
 
 pub const SRC_DIR:       &str = "src";
 pub const THEME_DIR:     &str = "theme";
+pub const STATIC_DIR:    &str = "static";
+pub const DATA_DIR:      &str = "data";
+pub const THEMES_DIR:    &str = "themes";
+pub const TEMPLATES_DIR: &str = "templates";
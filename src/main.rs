@@ -27,33 +27,46 @@ fn run(m: Matches, action: &str) -> Result<()> {
         return Ok(());
     }
 
-    let mut conf = Conf::from_file(m.opt_str("conf")
-                                    .unwrap_or(izzet::CONFIG_FILE.to_string()))?;
+    let conf_path = m.opt_str("conf").unwrap_or(izzet::CONFIG_FILE.to_string());
+    let mut conf = Conf::from_file(&conf_path)?;
 
     if m.opt_present("force") {
         conf.force = Some(true)
     }
-    if let None = conf.title {
-        conf.title = Some("Default title".to_string());
+    if m.opt_present("drafts") {
+        conf.drafts = Some(true)
+    }
+    if m.opt_present("full") {
+        conf.full = Some(true)
+    }
+    if m.opt_present("prune") || m.opt_present("dry-run") {
+        let mut prune = conf.prune.take().unwrap_or_default();
+        prune.enabled = Some(true);
+        if m.opt_present("dry-run") {
+            prune.dry_run = Some(true);
+        }
+        conf.prune = Some(prune);
     }
 
     match action {
         "article" => {
             let path = m.free.get(1)
                 .ok_or(Error::new("need specify path to the article".to_string()))?;
-            post::create_post(path.to_string(), conf, PostKind::Article)?;
+            post::create_post(path.to_string(), conf, PostKind::Article,
+                              m.opt_present("drafts"))?;
         },
 
         "page" => {
             let path = m.free.get(1)
                 .ok_or(Error::new("need specify path to the page".to_string()))?;
-            post::create_post(path.to_string(), conf, PostKind::Page)?;
+            post::create_post(path.to_string(), conf, PostKind::Page,
+                              m.opt_present("drafts"))?;
         },
 
         "gen" => {
             conf.in_dir = m.opt_str("input");
             conf.out_dir = m.opt_str("output");
-            Site::collect(&conf).and_then(|s| s.generate(&conf))?;
+            Site::collect(&conf).and_then(|s| s.generate())?;
         },
 
         "server" => {
@@ -62,7 +75,13 @@ fn run(m: Matches, action: &str) -> Result<()> {
                 .unwrap_or(env::current_dir()?);
             conf.port = m.opt_str("listen")
                 .and_then(|s| s.parse::<u16>().ok());
-            server::forever(dir, conf)?;
+            if m.opt_present("watch") {
+                conf.in_dir = m.opt_str("input");
+                server::watch(dir, PathBuf::from(conf_path), conf)?;
+            }
+            else {
+                server::forever(dir, conf)?;
+            }
         },
 
         _ => {
@@ -84,6 +103,19 @@ fn main() {
                               to specify the input and output location.");
     opts.optflag("s", "server", "Start a local server to preview the generated site \
                                  specified by a directory.");
+    opts.optflag("w", "watch", "Used along with -s, generate the site into the served \
+                                directory and regenerate it whenever the sources, \
+                                the theme or the configuration change, reloading \
+                                the pages open in the browser.");
+    opts.optflag("d", "drafts", "Include draft posts when generating or serving \
+                                 the site, or create posts as drafts.");
+    opts.optflag("", "full", "Regenerate the whole site, ignoring what is left \
+                              unchanged since the last generation.");
+    opts.optflag("", "prune", "Remove the files in the output directory which are not \
+                               generated by this build, leaving alone those kept by \
+                               the configuration.");
+    opts.optflag("", "dry-run", "List the files which --prune would remove \
+                                 without removing them.");
     opts.optflag("f", "force", "Overwrite existing files when creating articles, \
                                 generating site output files, etc.");
 
@@ -1,9 +1,389 @@
+use conf::Conf;
 use error::Result;
-use pulldown_cmark::{Parser, html};
+use feed::escape;
+use highlight::{self, HighlightConf};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, html};
+use slug::slugify;
+use std::collections::HashSet;
 
-pub fn markdown_to_html(md: &str) -> Result<String> {
-    let parser = Parser::new(md);
-    let mut ret = String::new();
-    html::push_html(&mut ret, parser);
+const DEFAULT_TOC_DEPTH: usize = 3;
+const DEFAULT_SUMMARY_WORDS: usize = 50;
+const MORE_MARK: &str = "<!-- more -->";
+
+// CommonMark extensions, all of which are disabled unless turned on,
+// how headings are treated and how long summaries are.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MarkdownConf {
+    pub tables: Option<bool>,
+    pub footnotes: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub tasklists: Option<bool>,
+    pub toc: Option<bool>,
+    pub toc_depth: Option<usize>,
+    pub anchors: Option<bool>,
+    pub summary_words: Option<usize>,
+}
+
+impl MarkdownConf {
+    // Options set in `other` win over those set in `self`.
+    pub fn merge(&self, other: &MarkdownConf) -> MarkdownConf {
+        MarkdownConf {
+            tables: other.tables.or(self.tables),
+            footnotes: other.footnotes.or(self.footnotes),
+            strikethrough: other.strikethrough.or(self.strikethrough),
+            tasklists: other.tasklists.or(self.tasklists),
+            toc: other.toc.or(self.toc),
+            toc_depth: other.toc_depth.or(self.toc_depth),
+            anchors: other.anchors.or(self.anchors),
+            summary_words: other.summary_words.or(self.summary_words),
+        }
+    }
+
+    pub fn options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, self.tables.unwrap_or(false));
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes.unwrap_or(false));
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough.unwrap_or(false));
+        options.set(Options::ENABLE_TASKLISTS, self.tasklists.unwrap_or(false));
+        options
+    }
+}
+
+// A heading in the table of contents, with the headings of lower levels
+// following it until the next one of the same or a higher level.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: usize,
+    pub id: String,
+    // text of the heading, escaped
+    pub title: String,
+    pub children: Vec<Heading>,
+}
+
+impl Heading {
+    fn push(toc: &mut Vec<Heading>, h: Heading) {
+        match toc.last_mut() {
+            Some(ref mut last) if last.level < h.level => Heading::push(&mut last.children, h),
+            _ => toc.push(h),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Markdown {
+    pub html: String,
+    pub toc: Vec<Heading>,
+    // `None` if the summary would be all of it
+    pub summary: Option<String>,
+}
+
+// Give headings an `id` made from their text, unique within the document,
+// and optionally a link to themselves, collecting them into a table of contents.
+fn add_heading_ids<'a, I>(events: I, conf: &MarkdownConf) -> (Vec<Event<'a>>, Vec<Heading>)
+        where I: Iterator<Item = Event<'a>>
+{
+    let depth = conf.toc_depth.unwrap_or(DEFAULT_TOC_DEPTH);
+    let mut ret = vec![];
+    let mut toc = vec![];
+    let mut ids = HashSet::new();
+    // level, text and events of the heading being read
+    let mut heading = None;
+    for event in events {
+        match (event, heading.as_mut()) {
+            (Event::Start(Tag::Heading(level, _, _)), _) => {
+                heading = Some((level as usize, String::new(), vec![]))
+            },
+            (Event::End(Tag::Heading(..)), Some(_)) => {
+                let (level, text, inner) = heading.take().unwrap();
+                let slug = match slugify(&text) {
+                    ref s if s.is_empty() => "section".to_string(),
+                    s => s,
+                };
+                let id = (0..)
+                    .map(|n| if n == 0 { slug.clone() } else { format!("{}-{}", slug, n) })
+                    .find(|id| !ids.contains(id))
+                    .unwrap();
+                ids.insert(id.clone());
+
+                ret.push(Event::Html(format!("<h{} id=\"{}\">", level, id).into()));
+                ret.extend(inner);
+                if conf.anchors.unwrap_or(false) {
+                    ret.push(Event::Html(
+                        format!(" <a class=\"anchor\" href=\"#{}\">#</a>", id).into()));
+                }
+                ret.push(Event::Html(format!("</h{}>\n", level).into()));
+
+                if level <= depth {
+                    Heading::push(&mut toc, Heading { level, id, title: escape(&text),
+                                                      children: vec![] });
+                }
+            },
+            (event, Some(&mut (_, ref mut text, ref mut inner))) => {
+                if let Event::Text(ref s) | Event::Code(ref s) = event {
+                    text.push_str(s);
+                }
+                inner.push(event);
+            },
+            (event, None) => ret.push(event),
+        }
+    }
+    (ret, toc)
+}
+
+// Replace fenced code blocks in a known language with their highlighted HTML.
+fn highlight_code<'a, I>(events: I, conf: &HighlightConf) -> Result<Vec<Event<'a>>>
+        where I: Iterator<Item = Event<'a>>
+{
+    let mut ret = vec![];
+    // fence info and code of the fenced code block being read
+    let mut block = None;
+    for event in events {
+        match (event, block.as_mut()) {
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), _) => {
+                block = Some((info, String::new()))
+            },
+            (Event::Text(text), Some(&mut (_, ref mut code))) => code.push_str(&text),
+            (Event::End(Tag::CodeBlock(_)), Some(_)) => {
+                let (info, code) = block.take().unwrap();
+                match highlight::highlight(&code, &info, conf)? {
+                    Some(html) => ret.push(Event::Html(html.into())),
+                    None => {
+                        let kind = CodeBlockKind::Fenced(info);
+                        ret.push(Event::Start(Tag::CodeBlock(kind.clone())));
+                        ret.push(Event::Text(code.into()));
+                        ret.push(Event::End(Tag::CodeBlock(kind)));
+                    },
+                }
+            },
+            (event, _) => ret.push(event),
+        }
+    }
     Ok(ret)
 }
+
+// Byte index of the end of the `n`-th word in `text`.
+fn word_end(text: &str, n: usize) -> usize {
+    if n == 0 {
+        return 0;
+    }
+    let mut words = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if !c.is_whitespace() {
+            in_word = true;
+            continue;
+        }
+        if in_word {
+            words += 1;
+            if words == n {
+                return i;
+            }
+        }
+        in_word = false;
+    }
+    text.len()
+}
+
+// Take the events before the `<!-- more -->` marker if there is one,
+// or those of the first `words` words otherwise, closing the tags left open.
+// `None` if that would be all of them.
+fn summarize<'a>(events: &[Event<'a>], words: usize) -> Option<Vec<Event<'a>>> {
+    let marker = events.iter().position(|e| match *e {
+        Event::Html(ref s) => s.trim() == MORE_MARK,
+        _ => false,
+    });
+    let words = if marker.is_some() { usize::MAX } else { words };
+
+    let mut ret = vec![];
+    let mut open = vec![];
+    let mut count = 0;
+    for event in &events[..marker.unwrap_or(events.len())] {
+        // only the tags left open are still needed
+        if count >= words {
+            match *event {
+                Event::End(_) => {},
+                _ => break,
+            }
+        }
+        match *event {
+            Event::Start(ref tag) => open.push(tag.clone()),
+            Event::End(_) => {
+                open.pop();
+            },
+            Event::Text(ref text) => {
+                let n = text.split_whitespace().count();
+                if count + n > words {
+                    let end = word_end(text, words - count);
+                    ret.push(Event::Text(text[..end].to_string().into()));
+                    break;
+                }
+                count += n;
+            },
+            _ => {},
+        }
+        ret.push(event.clone());
+    }
+
+    if marker.is_none() && ret.len() == events.len() {
+        return None;
+    }
+    ret.extend(open.into_iter().rev().map(Event::End));
+    Some(ret)
+}
+
+// Render `md` with the options of the site, overridden by
+// those of the post if any.
+pub fn render(md: &str, conf: &Conf, post: Option<&MarkdownConf>) -> Result<Markdown> {
+    let mut options = conf.markdown.clone().unwrap_or_default();
+    if let Some(post) = post {
+        options = options.merge(post);
+    }
+
+    let mut events = Parser::new_ext(md, options.options()).collect::<Vec<_>>();
+    if let Some(ref hl) = conf.highlight {
+        events = highlight_code(events.into_iter(), hl)?;
+    }
+
+    // before the headings become HTML, which would be left open by a cut inside one,
+    // and without their ids, which would clash in a page with several summaries
+    let summary = summarize(&events, options.summary_words.unwrap_or(DEFAULT_SUMMARY_WORDS))
+        .map(|e| {
+            let mut summary = String::new();
+            html::push_html(&mut summary, e.into_iter());
+            summary
+        });
+
+    let mut toc = vec![];
+    if options.toc.unwrap_or(false) {
+        let (e, t) = add_heading_ids(events.into_iter(), &options);
+        events = e;
+        toc = t;
+    }
+
+    let mut html = String::new();
+    html::push_html(&mut html, events.into_iter());
+    Ok(Markdown { html, toc, summary })
+}
+
+pub fn markdown_to_html(md: &str, conf: &Conf, post: Option<&MarkdownConf>) -> Result<String> {
+    render(md, conf, post).map(|m| m.html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_code() {
+        let md = "```rust\nfn main() {}\n```\n\n```nope\n<x>\n```\n\n    indented\n";
+        let mut c = Conf::default();
+
+        let html = markdown_to_html(md, &c, None).unwrap();
+        assert!(html.contains("<code class=\"language-rust\">fn main() {}"));
+
+        c.highlight = Some(HighlightConf::default());
+        let html = markdown_to_html(md, &c, None).unwrap();
+        assert!(!html.contains("language-rust"));
+        assert!(html.contains("<pre style="));
+        assert!(html.contains("<code class=\"language-nope\">&lt;x&gt;"));
+        assert!(html.contains("<pre><code>indented"));
+    }
+
+    #[test]
+    fn test_extensions() {
+        let md = "a | b\n--|--\n1 | 2\n\n~~gone~~ [^1]\n\n- [x] done\n\n[^1]: note\n";
+        let mut c = Conf::default();
+
+        let html = markdown_to_html(md, &c, None).unwrap();
+        assert!(!html.contains("<table>"));
+        assert!(!html.contains("<del>"));
+        assert!(!html.contains("footnote-definition"));
+
+        c.markdown = Some(MarkdownConf { tables: Some(true), footnotes: Some(true),
+                                         strikethrough: Some(true), tasklists: Some(true),
+                                         ..MarkdownConf::default() });
+        let html = markdown_to_html(md, &c, None).unwrap();
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<del>gone</del>"));
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+        assert!(html.contains("class=\"footnote-definition\""));
+
+        // the site turns tables on, the post turns them back off
+        let post = MarkdownConf { tables: Some(false), ..MarkdownConf::default() };
+        let html = markdown_to_html(md, &c, Some(&post)).unwrap();
+        assert!(!html.contains("<table>"));
+        assert!(html.contains("<del>gone</del>"));
+    }
+
+    #[test]
+    fn test_toc() {
+        let md = "# Intro\n\n## A `b` & c\n\n### Deep\n\n#### Deeper\n\n## Intro\n\n# Intro\n";
+        let mut c = Conf::default();
+
+        let m = render(md, &c, None).unwrap();
+        assert!(m.html.contains("<h1>Intro</h1>"));
+        assert!(m.toc.is_empty());
+
+        c.markdown = Some(MarkdownConf { toc: Some(true), ..MarkdownConf::default() });
+        let m = render(md, &c, None).unwrap();
+        assert!(m.html.contains("<h1 id=\"intro\">Intro</h1>"));
+        assert!(m.html.contains("<h2 id=\"a-b-c\">A <code>b</code> &amp; c</h2>"));
+        assert!(m.html.contains("<h2 id=\"intro-1\">"));
+        assert!(m.html.contains("<h1 id=\"intro-2\">"));
+        assert!(m.html.contains("<h4 id=\"deeper\">"));
+        assert!(!m.html.contains("class=\"anchor\""));
+
+        assert!(m.toc.len() == 2);
+        assert!(m.toc[0].children.len() == 2);
+        assert!(m.toc[0].children[0].title == "A b &amp; c");
+        // deeper than the default depth
+        assert!(m.toc[0].children[0].children[0].children.is_empty());
+
+        c.markdown = Some(MarkdownConf { toc: Some(true), anchors: Some(true), toc_depth: Some(1),
+                                         ..MarkdownConf::default() });
+        let m = render(md, &c, None).unwrap();
+        assert!(m.html.contains(
+            "<h1 id=\"intro\">Intro <a class=\"anchor\" href=\"#intro\">#</a></h1>"));
+        assert!(m.toc.len() == 2 && m.toc[0].children.is_empty());
+
+        let post = MarkdownConf { toc: Some(false), ..MarkdownConf::default() };
+        let m = render(md, &c, Some(&post)).unwrap();
+        assert!(m.html.contains("<h1>Intro</h1>"));
+        assert!(m.toc.is_empty());
+    }
+
+    #[test]
+    fn test_summary() {
+        let c = Conf {
+            markdown: Some(MarkdownConf { summary_words: Some(3), ..MarkdownConf::default() }),
+            ..Conf::default()
+        };
+
+        let m = render("one *two three four*\n\nfive\n", &c, None).unwrap();
+        assert!(m.summary == Some("<p>one <em>two three</em></p>\n".to_string()));
+
+        // stop between paragraphs if possible
+        let m = render("one two three\n\nfour\n", &c, None).unwrap();
+        assert!(m.summary == Some("<p>one two three</p>\n".to_string()));
+
+        let m = render("one two three\n", &c, None).unwrap();
+        assert!(m.summary.is_none());
+
+        // the marker wins over the number of words
+        let md = "one two three four\n\n<!-- more -->\n\nfive\n";
+        let m = render(md, &c, None).unwrap();
+        assert!(m.summary == Some("<p>one two three four</p>\n".to_string()));
+        let m = render("- one <!-- more --> two\n", &c, None).unwrap();
+        assert!(m.summary == Some("<ul>\n<li>one </li>\n</ul>\n".to_string()));
+
+        // cut inside a heading with an id
+        let c = Conf {
+            markdown: Some(MarkdownConf { toc: Some(true), anchors: Some(true),
+                                          summary_words: Some(3), ..MarkdownConf::default() }),
+            ..Conf::default()
+        };
+        let m = render("## A rather long *heading*\n\nfive\n", &c, None).unwrap();
+        assert!(m.html.starts_with("<h2 id=\"a-rather-long-heading\">"));
+        assert!(m.summary == Some("<h2>A rather long </h2>\n".to_string()));
+    }
+}
@@ -0,0 +1,124 @@
+use error::{Error, Result};
+use post::Post;
+use slug::slugify;
+use std::collections::BTreeMap;
+use taxonomy::Taxonomy;
+
+const DEFAULT_RELATED: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NavConf {
+    // "section" or "tag" to keep `prev` and `next` within the same one
+    pub within: Option<String>,
+    pub related: Option<usize>,
+}
+
+impl NavConf {
+    pub fn check(&self) -> Result<()> {
+        match self.within.as_deref() {
+            None | Some("section") | Some("tag") => Ok(()),
+            Some(s) => Err(Error::new(format!("unknown nav.within {:?}, \
+                                               expecting \"section\" or \"tag\"", s))),
+        }
+    }
+
+    pub fn related(&self) -> usize {
+        self.related.unwrap_or(DEFAULT_RELATED)
+    }
+}
+
+// Links from an article to others, which are assumed to be sorted
+// from the newest to the oldest as `Site` does.
+#[derive(Serialize, Debug, Default)]
+pub struct Nav<'a> {
+    // the article before this one and the one after
+    pub prev: Option<&'a Post>,
+    pub next: Option<&'a Post>,
+    // articles sharing the most terms with this one
+    pub related: Vec<&'a Post>,
+}
+
+impl<'a> Nav<'a> {
+    pub fn new(conf: &NavConf, articles: &'a [Post], taxonomies: &[Taxonomy],
+               i: usize) -> Self {
+        let a = &articles[i];
+        let tags = a.tags.iter().map(slugify).collect::<Vec<_>>();
+        let neighbor = |p: &&Post| match conf.within.as_deref() {
+            Some("section") => p.section == a.section,
+            Some("tag") => p.tags.iter().any(|t| tags.contains(&slugify(t))),
+            _ => true,
+        };
+
+        // number of terms shared with each other article
+        let mut shared = BTreeMap::new();
+        for tax in taxonomies {
+            for j in tax.sharing(i).into_iter().filter(|&j| j != i) {
+                *shared.entry(j).or_insert(0) += 1;
+            }
+        }
+        let mut related = shared.into_iter().collect::<Vec<_>>();
+        // the newer first among those sharing as many terms
+        related.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+
+        Nav {
+            prev: articles[i + 1..].iter().find(&neighbor),
+            next: articles[..i].iter().rev().find(&neighbor),
+            related: related.into_iter()
+                .take(conf.related())
+                .map(|(j, _)| &articles[j])
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(section: &str, tags: &[&str]) -> Post {
+        let mut p = Post::new();
+        p.section = section.to_string();
+        p.meta.tags = tags.iter().map(|t| t.to_string()).collect();
+        p
+    }
+
+    #[test]
+    fn test_nav() {
+        let articles = vec![
+            article("a", &["x", "y"]),
+            article("b", &["y"]),
+            article("a", &["z"]),
+            article("b", &["x", "y"]),
+        ];
+        let taxonomies = Taxonomy::from_articles(&articles);
+        let mut c = NavConf::default();
+
+        let nav = Nav::new(&c, &articles, &taxonomies, 1);
+        assert!(nav.next.unwrap().section == "a" && nav.next.unwrap().tags == vec!["x", "y"]);
+        assert!(nav.prev.unwrap().tags == vec!["z"]);
+        assert!(Nav::new(&c, &articles, &taxonomies, 0).next.is_none());
+
+        // two terms in common come before one, then the newer
+        let nav = Nav::new(&c, &articles, &taxonomies, 0);
+        assert!(nav.related.len() == 2);
+        assert!(nav.related[0].tags == vec!["x", "y"] && nav.related[1].tags == vec!["y"]);
+
+        c.within = Some("section".to_string());
+        let nav = Nav::new(&c, &articles, &taxonomies, 1);
+        assert!(nav.next.is_none());
+        assert!(nav.prev.unwrap().tags == vec!["x", "y"]);
+
+        // sharing any tag
+        c.within = Some("tag".to_string());
+        let nav = Nav::new(&c, &articles, &taxonomies, 0);
+        assert!(nav.prev.unwrap().tags == vec!["y"]);
+        let nav = Nav::new(&c, &articles, &taxonomies, 2);
+        assert!(nav.prev.is_none() && nav.next.is_none());
+
+        c.related = Some(1);
+        assert!(Nav::new(&c, &articles, &taxonomies, 0).related.len() == 1);
+
+        c.within = Some("year".to_string());
+        assert!(c.check().is_err());
+    }
+}
@@ -5,23 +5,80 @@ use std::fs;
 use std::path::PathBuf;
 use toml;
 
+const DEFAULT_PAGINATE: usize = 10;
+
 // HTML for the default template
 pub const POST_HTML: &[u8] = b"\
 <!DOCTYPE html>
 <html>
 <head>
   <meta charset=\"utf-8\">
+  {% if conf.highlight %}{% if conf.highlight.css %}
+  <link rel=\"stylesheet\"
+        href=\"{% if conf.highlight.stylesheet %}/{{ conf.highlight.stylesheet }}{% else %}/highlight.css{% endif %}\">
+  {% endif %}{% endif %}
 </head>
 <body>
   <h1><a href=\"/\">{{ post.meta.title }}</a></h1>
+  {% if post.meta.draft %}
+  <p><strong>Draft</strong></p>
+  {% endif %}
   <h3>{{ post.meta.ts | date(format=\"%Y-%b-%d\") }}</h3>
+  {% if post.toc %}
+  <ul>
+    {% for h in post.toc %}
+    <li>
+      <a href=\"#{{ h.id }}\">{{ h.title }}</a>
+      {% if h.children %}
+      <ul>
+        {% for c in h.children %}
+        <li><a href=\"#{{ c.id }}\">{{ c.title }}</a></li>
+        {% endfor %}
+      </ul>
+      {% endif %}
+    </li>
+    {% endfor %}
+  </ul>
+  {% endif %}
   <div>
     <div>
       {{ post.content }}
     </div>
   </div>
+  {% if post.meta.tags %}
+  <div>
+    Tags:
+    {% for tag in post.meta.tags %}
+    <a href=\"/tags/{{ tag | slugify }}.html\">{{ tag }}</a>
+    {% endfor %}
+  </div>
+  {% endif %}
+  {% if post.meta.categories %}
+  <div>
+    Categories:
+    {% for category in post.meta.categories %}
+    <a href=\"/categories/{{ category | slugify }}.html\">{{ category }}</a>
+    {% endfor %}
+  </div>
+  {% endif %}
+  {% if related %}
   <div>
+    Related:
+    <ul>
+      {% for r in related %}
+      <li><a href=\"{{ url_for(post=r) }}\">{{ r.meta.title }}</a></li>
+      {% endfor %}
+    </ul>
+  </div>
+  {% endif %}
+  <div>
+    {% if prev %}
+    <a href=\"{{ url_for(post=prev) }}\">Previous: {{ prev.meta.title }}</a>
+    {% endif %}
     <a href=\"/\">Home</a>
+    {% if next %}
+    <a href=\"{{ url_for(post=next) }}\">Next: {{ next.meta.title }}</a>
+    {% endif %}
   </div>
 </body>
 </html>
@@ -32,6 +89,14 @@ pub const INDEX_HTML: &[u8] = b"\
 <html>
 <head>
   <meta charset=\"utf-8\">
+  {% if conf.highlight %}{% if conf.highlight.css %}
+  <link rel=\"stylesheet\"
+        href=\"{% if conf.highlight.stylesheet %}/{{ conf.highlight.stylesheet }}{% else %}/highlight.css{% endif %}\">
+  {% endif %}{% endif %}
+  {% if conf.feed %}
+  <link rel=\"alternate\" type=\"application/atom+xml\"
+        href=\"{% if conf.feed.path %}/{{ conf.feed.path }}{% else %}/atom.xml{% endif %}\">
+  {% endif %}
 </head>
 <body>
   <h1><a href=\"/\">{{ conf.title }}</a></h1>
@@ -39,26 +104,36 @@ pub const INDEX_HTML: &[u8] = b"\
     <ul>
       <li><a href=\"/\">Home</a></li>
       <li><a href=\"/archive.html\">Archive</a></li>
+      <li><a href=\"/tags/index.html\">Tags</a></li>
+      <li><a href=\"/categories/index.html\">Categories</a></li>
       {% for page in pages %}
-        <li><a href=\"/{{ page.meta.link }}.html\">{{ page.meta.title }}</a></li>
+        <li><a href=\"{{ url_for(post=page) }}\">{{ page.meta.title }}</a></li>
       {% endfor %}
     </ul>
   </div>
-  {% if latest_article %}
+  {% for article in paginator.items %}
   <h2>
-    <a href=\"/{{ latest_article.meta.ts | date(format=\"%Y/%m/%d\") }}/{{ latest_article.meta.link }}.html\">
-    {{ latest_article.meta.title }}
+    <a href=\"{{ url_for(post=article) }}\">
+    {{ article.meta.title }}
     </a>
   </h2>
   <div>
-    <h3>{{ latest_article.meta.ts | date(format=\"%Y-%b-%d\") }}</h3>
+    <h3>{{ article.meta.ts | date(format=\"%Y-%b-%d\") }}</h3>
   </div>
   <div>
     <div>
-      {{ latest_article.content }}
+      {{ article.summary }}
     </div>
+    {% if article.has_more %}
+    <a href=\"{{ url_for(post=article) }}\">Read more</a>
+    {% endif %}
+  </div>
+  {% endfor %}
+  <div>
+    {% if paginator.prev %}<a href=\"{{ paginator.prev }}\">Newer</a>{% endif %}
+    <span>Page {{ paginator.current }} of {{ paginator.total }}</span>
+    {% if paginator.next %}<a href=\"{{ paginator.next }}\">Older</a>{% endif %}
   </div>
-  {% endif %}
 </body>
 </html>
 ";
@@ -73,10 +148,88 @@ pub const ARCHIVE_HTML: &[u8] = b"\
   <h1><a href=\"/\">{{ conf.title }}</a></h1>
   <div>
     <ul>
-    {% for article in articles %}
+    {% for article in paginator.items %}
+    <li>
+      <span>{{ article.meta.ts | date(format=\"%Y-%b-%d\") }}</span>
+      <a href=\"{{ url_for(post=article) }}\">
+      {{ article.meta.title }}
+      </a>
+      {% if article.meta.draft %}<strong>Draft</strong>{% endif %}
+    </li>
+    {% endfor %}
+    </ul>
+  </div>
+  <div>
+    {% if paginator.prev %}<a href=\"{{ paginator.prev }}\">Newer</a>{% endif %}
+    {% if paginator.next %}<a href=\"{{ paginator.next }}\">Older</a>{% endif %}
+  </div>
+</body>
+</html>
+";
+
+pub const SECTION_HTML: &[u8] = b"\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset=\"utf-8\">
+</head>
+<body>
+  <h1><a href=\"/\">{{ conf.title }}</a></h1>
+  <h2>{{ section.name }}</h2>
+  <div>
+    <ul>
+    {% for article in section.articles %}
+    <li>
+      <span>{{ article.meta.ts | date(format=\"%Y-%b-%d\") }}</span>
+      <a href=\"{{ url_for(post=article) }}\">
+      {{ article.meta.title }}
+      </a>
+    </li>
+    {% endfor %}
+    </ul>
+  </div>
+</body>
+</html>
+";
+
+pub const TAXONOMY_HTML: &[u8] = b"\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset=\"utf-8\">
+</head>
+<body>
+  <h1><a href=\"/\">{{ conf.title }}</a></h1>
+  <h2>{{ taxonomy | capitalize }}</h2>
+  <div>
+    <ul>
+    {% for term in terms %}
+    <li>
+      <a href=\"{{ term.url }}\">{{ term.name }}</a>
+      <span>({{ term.articles | length }})</span>
+    </li>
+    {% endfor %}
+    </ul>
+  </div>
+</body>
+</html>
+";
+
+pub const TERM_HTML: &[u8] = b"\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset=\"utf-8\">
+</head>
+<body>
+  <h1><a href=\"/\">{{ conf.title }}</a></h1>
+  <h2>{{ term.name }}</h2>
+  <div>
+    <ul>
+    {% for article in term.articles %}
     <li>
       <span>{{ article.meta.ts | date(format=\"%Y-%b-%d\") }}</span>
-      <a href=\"/{{ article.meta.ts | date(format=\"%Y/%m/%d\") }}/{{ article.meta.link }}.html\">
+      <a href=\"{{ url_for(post=article) }}\">
       {{ article.meta.title }}
       </a>
     </li>
@@ -95,13 +248,14 @@ pub fn create_site(dir: PathBuf, force: bool) -> Result<()> {
     let site_dirs = &[
         ::SRC_DIR,
         ::THEME_DIR,
+        ::STATIC_DIR,
     ];
     for d in site_dirs {
         let p = dir.join(d);
         fs::create_dir_all(&p).context(format!("error creating {:?}", p))?;
     }
 
-    let conf: Conf = Conf::default();
+    let conf = Conf { paginate: Some(DEFAULT_PAGINATE), ..Conf::default() };
     let conf = toml::to_string(&conf)?;
     files::fwrite(&dir.join(::CONFIG_FILE), conf.as_bytes(), force)?;
 
@@ -113,9 +267,12 @@ pub fn create_site(dir: PathBuf, force: bool) -> Result<()> {
     }
 
     let site_templates = &[
-        (::POST_FILE,    POST_HTML),
-        (::INDEX_FILE,   INDEX_HTML),
-        (::ARCHIVE_FILE, ARCHIVE_HTML),
+        (::POST_FILE,     POST_HTML),
+        (::INDEX_FILE,    INDEX_HTML),
+        (::ARCHIVE_FILE,  ARCHIVE_HTML),
+        (::SECTION_FILE,  SECTION_HTML),
+        (::TAXONOMY_FILE, TAXONOMY_HTML),
+        (::TERM_FILE,     TERM_HTML),
     ];
     for &(f, html) in site_templates {
         files::fwrite(&dir.join(::THEME_DIR).join(f), html, force)?;
@@ -137,6 +294,7 @@ mod tests {
         assert!(dir.join(::NOJEKYLL_FILE).exists());
         assert!(dir.join(::SRC_DIR).exists());
         assert!(dir.join(::THEME_DIR).exists());
+        assert!(dir.join(::STATIC_DIR).exists());
         fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -2,11 +2,15 @@ use chrono::{DateTime, Datelike, Local};
 use conf::Conf;
 use error::{Error, Result, ResultContext};
 use files;
-use markdown;
+use markdown::{self, Heading, Markdown, MarkdownConf};
+use serde_json::{self, Value};
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
-use std::io::{Read, BufRead, BufReader};
+use std::io::Read;
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str;
 use std::string::String;
 use tera::{Tera, Context};
@@ -14,19 +18,142 @@ use toml;
 
 const POST_META_MARK: &str = "%%%\n";
 
+// How the metadata of a post is written before its content.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontMatter {
+    // TOML followed by `%%%`
+    #[default]
+    Izzet,
+    // TOML, YAML or JSON between `+++`, `---` or braces
+    Toml,
+    Yaml,
+    Json,
+}
+
+fn parse_error<E: fmt::Display>(e: E) -> Error {
+    Error::new(e.to_string())
+}
+
+// The text between the first line of `src` and the next line equal
+// to it if the first line is `mark`, and what follows.
+fn fenced<'a>(src: &'a str, mark: &str) -> Option<(&'a str, &'a str)> {
+    let mut lines = src.split_inclusive('\n');
+    let first = lines.next()?;
+    if first.trim_end() != mark {
+        return None;
+    }
+    let start = first.len();
+    let mut end = start;
+    for line in lines {
+        if line.trim_end() == mark {
+            return Some((&src[start..end], &src[end + line.len()..]));
+        }
+        end += line.len();
+    }
+    None
+}
+
+// Whether each line of `text` not indented starts with `key:`,
+// as metadata written in YAML does.
+fn looks_like_yaml(text: &str) -> bool {
+    let mut lines = text.lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with([' ', '\t', '#', '-']))
+        .peekable();
+    lines.peek().is_some() && lines.all(|l| {
+        let key = l.split(':').next().unwrap();
+        l.len() > key.len() && !key.is_empty()
+            && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+impl FrontMatter {
+    // Split `src` into the metadata and the content, `None` if it has no metadata.
+    // What is between `---` lines is only taken as metadata if it is a table, or
+    // looks like one, so that e.g. Markdown with horizontal rules isn't.
+    pub fn split(src: &str) -> Option<(Result<PostMeta>, &str)> {
+        if let Some((meta, content)) = fenced(src, "+++") {
+            return Some((toml::from_str(meta).map_err(parse_error), content));
+        }
+        if let Some((meta, content)) = fenced(src, "---") {
+            match serde_yaml::from_str(meta) {
+                Ok(meta @ serde_yaml::Value::Mapping(_)) => {
+                    return Some((serde_yaml::from_value(meta).map_err(parse_error), content));
+                },
+                Err(e) if looks_like_yaml(meta) => return Some((Err(parse_error(e)), content)),
+                _ => {},
+            }
+        }
+        // rather than e.g. `{{` of a template
+        if src.starts_with('{') && src[1..].trim_start().starts_with(['"', '}']) {
+            let mut stream = serde_json::Deserializer::from_str(src).into_iter();
+            let meta = stream.next()?.map_err(parse_error);
+            let content = &src[stream.byte_offset()..];
+            return Some((meta, content.strip_prefix('\n').unwrap_or(content)));
+        }
+
+        let mut end = 0;
+        for line in src.split_inclusive('\n') {
+            if line.trim_end() == POST_META_MARK.trim_end() {
+                return Some((toml::from_str(&src[..end]).map_err(parse_error),
+                             &src[end + line.len()..]));
+            }
+            end += line.len();
+        }
+        None
+    }
+
+    pub fn write(self, meta: &PostMeta) -> Result<String> {
+        Ok(match self {
+            FrontMatter::Izzet => toml::to_string(meta)? + POST_META_MARK,
+            FrontMatter::Toml => format!("+++\n{}+++\n", toml::to_string(meta)?),
+            // which starts with `---` already
+            FrontMatter::Yaml => {
+                format!("{}\n---\n", serde_yaml::to_string(meta)?.trim_end())
+            },
+            FrontMatter::Json => serde_json::to_string_pretty(meta)? + "\n",
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum PostKind{
     Article,
     Page,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PostMeta {
     pub title: String,
     pub link: String,
     pub url: String,
     pub ts: DateTime<Local>,
+    // last time the post was changed significantly, if ever
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Local>>,
     pub kind: PostKind,
+    #[serde(default)]
+    pub draft: bool,
+    // whether the post is listed in the sitemap, yes if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sitemap: Option<bool>,
+    // summary in markdown, instead of the one taken from the content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    // template to render the post with instead of the default ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    // overrides of the markdown options of the site
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<MarkdownConf>,
+    // anything else for the theme to use, which as a table
+    // must come after other fields to be written as TOML
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, Value>,
 }
 
 const DEFAULT_TITLE: &str = "Default Title";
@@ -41,15 +168,34 @@ impl Default for PostMeta {
             link: DEFAULT_LINK.to_string(),
             url: DEFAULT_ARTICLE_URL.to_string(),
             ts: Local::now(),
+            updated: None,
             kind: PostKind::Article,
+            draft: false,
+            sitemap: None,
+            summary: None,
+            template: None,
+            tags: vec![],
+            categories: vec![],
+            markdown: None,
+            extra: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Post {
     pub meta: PostMeta,
     pub content: String,
+    // the part of the content shown in listings and feeds
+    pub summary: String,
+    // whether the summary leaves out some of the content
+    pub has_more: bool,
+    pub toc: Vec<Heading>,
+    // directory of the source relative to the source directory,
+    // with components separated by `/`
+    pub section: String,
+    #[serde(skip)]
+    pub path: PathBuf,
 }
 
 impl Default for Post {
@@ -57,6 +203,11 @@ impl Default for Post {
         Post {
             meta: PostMeta::default(),
             content: "".to_string(),
+            summary: "".to_string(),
+            has_more: false,
+            toc: vec![],
+            section: "".to_string(),
+            path: PathBuf::new(),
         }
     }
 }
@@ -73,26 +224,24 @@ impl Post {
         Post::default()
     }
 
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
-        let mut reader = File::open(&path)
-            .map(BufReader::new)
+    pub fn from_file<P: AsRef<Path>>(path: P, conf: &Conf) -> Result<Option<Self>> {
+        let mut src = vec![];
+        File::open(&path)
+            .and_then(|mut f| f.read_to_end(&mut src))
             .context(format!("error opening {:?}", path.as_ref()))?;
+        // not text, not a post
+        let src = match String::from_utf8(src) {
+            Ok(s) => s,
+            Err(_) => return Ok(None),
+        };
 
-        let mut meta = "".to_string();
-        let mut line = "".to_string();
-        loop {
-            match reader.read_line(&mut line) {
-                Ok(n) if n > 0 && &line != POST_META_MARK => {
-                    meta += &line;
-                    line.clear();
-                },
-                Ok(n) if n > 0 => break,
-                _ => return Ok(None),
-            }
-        }
-
-        let meta: PostMeta = toml::from_str(&meta)
-            .context(format!("error parsing metadata of {:?}", path.as_ref()))?;
+        let (meta, content) = match FrontMatter::split(&src) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let meta = meta
+            .map_err(|e| Error::new(format!("error parsing metadata of {:?}: {}",
+                                            path.as_ref(), e)))?;
 
         // XXX maybe add more metadata sanity check here
         // as later we'll lose the corresponding file path
@@ -101,34 +250,64 @@ impl Post {
             return Err(Error::new(format!("output URL of post {:?} is 0", path.as_ref())));
         }
 
-        let mut content = "".to_string();
-        reader.read_to_string(&mut content)
-              .context(format!("error reading content from {:?}", path.as_ref()))?;
+        let Markdown { html: content, toc, summary } = match path.as_ref()
+                                                        .extension()
+                                                        .and_then(|s| s.to_str()) {
+            Some("md") | Some("markdown") => {
+                markdown::render(content, conf, meta.markdown.as_ref())?
+            },
+            _ => Markdown { html: content.to_string(), ..Markdown::default() },
+        };
 
-        let content = match path.as_ref()
-                                .extension()
-                                .and_then(|s| s.to_str()) {
-            Some("md") | Some("markdown") => markdown::markdown_to_html(&content)?,
-            _ => content,
+        // a summary written by hand always leaves something more to read
+        let summary = match meta.summary {
+            Some(ref s) => Some(markdown::markdown_to_html(s, conf, meta.markdown.as_ref())?),
+            None => summary,
         };
+        let has_more = summary.is_some();
+        let summary = summary.unwrap_or(content.clone());
+
+        Ok(Some(Post {
+            meta,
+            content,
+            summary,
+            has_more,
+            toc,
+            section: "".to_string(),
+            path: path.as_ref().to_path_buf(),
+        }))
+    }
 
-        Ok(Some(Post { meta, content }))
+    pub fn lastmod(&self) -> DateTime<Local> {
+        self.updated.unwrap_or(self.ts)
     }
 
     pub fn url(&self) -> Result<String> {
-        let mut ctx = Context::new();
-        // call format to make sure the leading zero exists
-        ctx.add("year", &self.ts.year());
-        ctx.add("month", &self.ts.format("%m").to_string());
-        ctx.add("day", &self.ts.format("%d").to_string());
-        ctx.add("link", &self.link);
+        url(&self.url, &self.ts, &self.link, &self.section)
+    }
+}
 
-        Tera::one_off(&self.url, &ctx, false)
-            .map_err(Error::from)
+// The URL given by the `url` metadata of a post, a template of the other arguments.
+pub fn url(pattern: &str, ts: &DateTime<Local>, link: &str, section: &str) -> Result<String> {
+    let mut ctx = Context::new();
+    // call format to make sure the leading zero exists
+    ctx.add("year", &ts.year());
+    ctx.add("month", &ts.format("%m").to_string());
+    ctx.add("day", &ts.format("%d").to_string());
+    ctx.add("link", &link);
+    ctx.add("section", &section);
+
+    // an empty section leaves an empty path component
+    let mut url = Tera::one_off(pattern, &ctx, false)?;
+    while url.contains("//") {
+        url = url.replace("//", "/");
     }
+    Ok(url)
 }
 
-pub fn create_post<P: AsRef<Path>>(path: P, conf: Conf, kind: PostKind) -> Result<()> {
+// Write the metadata of a new post to `path`, marked as a draft if `draft`.
+pub fn create_post<P: AsRef<Path>>(path: P, conf: Conf, kind: PostKind,
+                                  draft: bool) -> Result<()> {
     let link = match path.as_ref()
                          .file_stem()
                          .and_then(|s| s.to_str()) {
@@ -143,8 +322,9 @@ pub fn create_post<P: AsRef<Path>>(path: P, conf: Conf, kind: PostKind) -> Resul
     };
     post.meta.kind = kind;
     post.meta.link = link.to_string();
+    post.meta.draft = draft;
 
-    let content = toml::to_string(&post.meta)? + POST_META_MARK;
+    let content = conf.front_matter.unwrap_or_default().write(&post.meta)?;
     files::fwrite(&path, content.as_bytes(), conf.force.unwrap_or(false))?;
 
     Ok(())
@@ -156,7 +336,6 @@ mod tests {
     use ::std::{env, fs};
     use ::std::fs::{OpenOptions, File, remove_file};
     use ::std::io::Write;
-    use ::std::path::PathBuf;
 
     fn assert_create(kind: PostKind) {
         let mut c = Conf::default();
@@ -164,18 +343,19 @@ mod tests {
         let just_now = Local::now();
 
         let path = env::temp_dir().join("x.md");
-        create_post(&path, c.clone(), kind.clone()).unwrap();
+        create_post(&path, c.clone(), kind.clone(), false).unwrap();
 
         fs::OpenOptions::new().append(true)
             .open(&path).unwrap()
             .write(b"XXX").unwrap();
 
-        let post = Post::from_file(&path).unwrap().unwrap();
+        let post = Post::from_file(&path, &c).unwrap().unwrap();
 
         assert!(just_now < post.ts && post.ts < Local::now());
         assert!(&post.link == "x");
         assert!(post.kind == kind);
-        assert!(post.content == markdown::markdown_to_html("XXX").unwrap());
+        assert!(!post.draft);
+        assert!(post.content == markdown::markdown_to_html("XXX", &c, None).unwrap());
 
         fs::remove_file(path).unwrap();
     }
@@ -186,6 +366,23 @@ mod tests {
         assert_create(PostKind::Page);
     }
 
+    #[test]
+    fn test_create_draft() {
+        let mut c = Conf::default();
+        c.force = Some(true);
+        c.drafts = Some(true);
+
+        // including drafts in builds doesn't make new posts drafts
+        let path = env::temp_dir().join("draft.md");
+        create_post(&path, c.clone(), PostKind::Article, false).unwrap();
+        assert!(!Post::from_file(&path, &c).unwrap().unwrap().draft);
+
+        create_post(&path, c.clone(), PostKind::Article, true).unwrap();
+        assert!(Post::from_file(&path, &c).unwrap().unwrap().draft);
+
+        fs::remove_file(path).unwrap();
+    }
+
     fn temp_src() -> (PathBuf, File) {
         let path = env::temp_dir().join("y.md");
         let file = OpenOptions::new()
@@ -195,11 +392,78 @@ mod tests {
         (path, file)
     }
 
+    #[test]
+    fn test_front_matter() {
+        for &style in &[FrontMatter::Izzet, FrontMatter::Toml, FrontMatter::Yaml,
+                        FrontMatter::Json] {
+            let c = Conf { force: Some(true), front_matter: Some(style), ..Conf::default() };
+
+            let path = env::temp_dir().join(format!("{:?}.md", style));
+            create_post(&path, c.clone(), PostKind::Article, false).unwrap();
+            let mut src = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+            src += "XXX\n";
+            fs::write(&path, &src).unwrap();
+
+            let post = Post::from_file(&path, &c).unwrap().unwrap();
+            assert!(post.link == format!("{:?}", style));
+            assert!(post.content == "<p>XXX</p>\n");
+
+            fs::remove_file(path).unwrap();
+        }
+
+        // broken metadata is reported rather than skipped
+        for src in &["---\ntitle: 1\n---\nXXX", "---\ntitle: [\n---\nXXX",
+                     "+++\ntitle = \n+++\nXXX", "+++\nno end\n+++\nXXX"] {
+            let (meta, content) = FrontMatter::split(src).unwrap();
+            assert!(meta.is_err() && content == "XXX");
+        }
+        assert!(FrontMatter::split("{\"title\": }\n").unwrap().0.is_err());
+        assert!(FrontMatter::split("---\nSome: text. And [more\n\nhere\n---\n").is_none());
+        assert!(FrontMatter::split("---\nno end\n").is_none());
+        assert!(FrontMatter::split("just text\n").is_none());
+        assert!(FrontMatter::split("{{ x }}\n").is_none());
+
+        // Markdown with horizontal rules isn't a post
+        let path = env::temp_dir().join("rules.md");
+        fs::write(&path, "---\nSome text.\n\n---\n\nMore text.\n").unwrap();
+        assert!(Post::from_file(&path, &Conf::default()).unwrap().is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_extra_meta() {
+        let c = Conf::default();
+        let path = env::temp_dir().join("extra.md");
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"title = \"xxx\"\n\
+                     link = \"yyy\"\n\
+                     url = \"/yyy.html\"\n\
+                     ts = \"2017-12-04T20:23:37.463860-05:00\"\n\
+                     kind = \"Page\"\n\
+                     [extra]\n\
+                     cover_image = \"a.png\"\n\
+                     hide_comments = true\n")
+            .unwrap();
+        file.write_all(POST_META_MARK.as_bytes()).unwrap();
+
+        let post = Post::from_file(&path, &c).unwrap().unwrap();
+        assert!(post.extra["cover_image"] == "a.png");
+        assert!(post.extra["hide_comments"] == true);
+
+        // and written back after other metadata
+        let meta = toml::to_string(&post.meta).unwrap();
+        assert!(meta.ends_with("[extra]\ncover_image = \"a.png\"\nhide_comments = true\n"));
+
+        remove_file(path).unwrap();
+    }
+
     #[test]
     fn test_post_with_bad_meta() {
+        let c = Conf::default();
+
         // no meta at all
         let (path, _) = temp_src();
-        let post = Post::from_file(&path);
+        let post = Post::from_file(&path, &c);
         assert!(post.is_ok());
         assert!(post.unwrap().is_none());
         remove_file(path).unwrap();
@@ -207,7 +471,7 @@ mod tests {
         // only a meta ending mark
         let (path, mut file) = temp_src();
         file.write(POST_META_MARK.as_bytes()).unwrap();
-        let post = Post::from_file(&path);
+        let post = Post::from_file(&path, &c);
         assert!(post.is_err());
         remove_file(path).unwrap();
 
@@ -221,7 +485,7 @@ mod tests {
             .unwrap();
         file.write(POST_META_MARK.as_bytes()).unwrap();
 
-        let post = Post::from_file(&path);
+        let post = Post::from_file(&path, &c);
         assert!(post.is_err());
 
         remove_file(path).unwrap();
@@ -0,0 +1,206 @@
+use error::{Result, ResultContext};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// files at the top of the output directory which are never removed, by the start
+// of their names in any case, besides hidden files like `.git` or `.nojekyll`
+const ALWAYS_KEEP: &[&str] = &["CNAME", "README", "LICENSE", "LICENCE", "COPYING",
+                               "CHANGELOG", "CONTRIBUTING"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PruneConf {
+    // whether to prune at all, which `--prune` and `--dry-run` turn on
+    pub enabled: Option<bool>,
+    // more paths relative to the output directory to leave alone
+    pub keep: Option<Vec<String>>,
+    // only tell what would be removed
+    pub dry_run: Option<bool>,
+}
+
+impl PruneConf {
+    pub fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run.unwrap_or(false)
+    }
+}
+
+struct Keep {
+    out_dir: PathBuf,
+    // whether the site is generated into its own directory
+    in_place: bool,
+    names: Vec<String>,
+    // inputs of the site if they are under the output directory
+    inputs: Vec<PathBuf>,
+}
+
+impl Keep {
+    fn new(out_dir: &Path, in_dir: &Path, conf: &PruneConf) -> Result<Self> {
+        let out_dir = out_dir.canonicalize().context(format!("error reading {:?}", out_dir))?;
+        let in_dir = in_dir.canonicalize().context(format!("error reading {:?}", in_dir))?;
+
+        let in_place = in_dir == out_dir;
+        let inputs = if in_place {
+            let mut inputs = [::SRC_DIR, ::THEME_DIR, ::THEMES_DIR, ::STATIC_DIR, ::DATA_DIR]
+                .iter()
+                .map(|d| in_dir.join(d))
+                .collect::<Vec<_>>();
+            // the configuration file may have any name given by `-c`
+            for e in fs::read_dir(&in_dir).context(format!("error reading {:?}", in_dir))? {
+                let path = e.context(format!("error reading {:?}", in_dir))?.path();
+                if path.extension().map(|e| e == "toml").unwrap_or(false) {
+                    inputs.push(path);
+                }
+            }
+            inputs
+        }
+        else if in_dir.starts_with(&out_dir) {
+            vec![in_dir]
+        }
+        else {
+            vec![]
+        };
+
+        Ok(Keep {
+            out_dir,
+            in_place,
+            names: conf.keep.iter().flatten().map(|k| k.trim_matches('/').to_string()).collect(),
+            inputs,
+        })
+    }
+
+    fn keeps(&self, path: &Path) -> bool {
+        let rel = path.strip_prefix(&self.out_dir).unwrap();
+        let hidden = rel.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        let always = rel.components().count() == 1 && {
+            let name = rel.to_string_lossy().to_uppercase();
+            ALWAYS_KEEP.iter().any(|k| name.starts_with(k))
+        };
+        hidden || always
+            || self.names.iter().any(|k| rel.starts_with(k))
+            || self.inputs.iter().any(|i| path.starts_with(i))
+    }
+}
+
+fn walk(dir: &Path, keep: &Keep, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .context(format!("error reading {:?}", dir))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<::std::io::Result<Vec<_>>>()
+        .context(format!("error reading {:?}", dir))?;
+    entries.sort();
+
+    for p in entries.into_iter().filter(|p| !keep.keeps(p)) {
+        if p.is_dir() {
+            walk(&p, keep, paths)?;
+        }
+        else {
+            paths.push(p);
+        }
+    }
+    Ok(())
+}
+
+// Files under `out_dir` other than those in `produced`, which are named
+// by their paths relative to it with `/` between the components, leaving
+// alone those to keep by `conf` and the inputs of the site under `in_dir`.
+// When `out_dir` is `in_dir` itself, which has all sorts of other files,
+// only those earlier builds wrote, named in `written`, may be stale.
+pub fn stale<P, Q>(out_dir: P, in_dir: Q, produced: &BTreeSet<String>,
+                   written: &BTreeSet<String>, conf: &PruneConf) -> Result<Vec<PathBuf>>
+        where P: AsRef<Path>, Q: AsRef<Path>
+{
+    let keep = Keep::new(out_dir.as_ref(), in_dir.as_ref(), conf)?;
+    let mut paths = vec![];
+    walk(&keep.out_dir, &keep, &mut paths)?;
+
+    Ok(paths.into_iter()
+        .filter(|p| {
+            let rel = p.strip_prefix(&keep.out_dir).unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            !produced.contains(&rel) && (!keep.in_place || written.contains(&rel))
+        })
+        .collect())
+}
+
+// Remove the stale files and then the directories left empty, or just
+// list them with `dry_run`. The files are returned either way.
+pub fn prune<P, Q>(out_dir: P, in_dir: Q, produced: &BTreeSet<String>,
+                   written: &BTreeSet<String>, conf: &PruneConf) -> Result<Vec<PathBuf>>
+        where P: AsRef<Path>, Q: AsRef<Path>
+{
+    let paths = stale(&out_dir, in_dir, produced, written, conf)?;
+    if conf.dry_run() {
+        for p in &paths {
+            println!("would remove {:?}", p);
+        }
+        return Ok(paths);
+    }
+
+    let out_dir = out_dir.as_ref().canonicalize()?;
+    let mut dirs = BTreeSet::new();
+    for p in &paths {
+        fs::remove_file(p).context(format!("error removing {:?}", p))?;
+        println!("removed {:?}", p);
+        dirs.extend(p.ancestors().skip(1).take_while(|d| *d != out_dir).map(PathBuf::from));
+    }
+    // the deepest first, which come after their parents
+    for d in dirs.iter().rev() {
+        if fs::read_dir(d).map(|mut e| e.next().is_none()).unwrap_or(false) {
+            fs::remove_dir(d).context(format!("error removing {:?}", d))?;
+        }
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::files;
+    use ::std::env;
+
+    #[test]
+    fn test_prune() {
+        let dir = env::temp_dir().join("prune");
+        for f in &["index.html", "old.html", "2018/01/old.html", "CNAME", ".git/HEAD",
+                   "keep/me.html", "src/a.md", "izzet.toml", ::CACHE_FILE, "README.md",
+                   "LICENSE-MIT", ".github/workflows/ci.yml", "docs/README.md"] {
+            files::fwrite(dir.join(f), b"", true).unwrap();
+        }
+        let produced = vec!["index.html".to_string()].into_iter().collect();
+        let written = ["index.html", "old.html", "2018/01/old.html", "gone.html"].iter()
+            .map(|f| f.to_string())
+            .collect();
+        let conf = PruneConf { keep: Some(vec!["/keep/".to_string()]), dry_run: Some(true),
+                               ..PruneConf::default() };
+        let out = dir.canonicalize().unwrap();
+
+        // only what was written before, in the site directory itself
+        let paths = prune(&dir, &dir, &produced, &written, &conf).unwrap();
+        assert!(paths == vec![out.join("2018").join("01").join("old.html"), out.join("old.html")]);
+        assert!(dir.join("old.html").exists());
+
+        // inputs only matter if they are generated into
+        let other = env::temp_dir().join("prune-in");
+        fs::create_dir_all(&other).unwrap();
+        assert!(stale(&dir, &other, &produced, &BTreeSet::new(), &conf).unwrap().len() == 5);
+
+        let conf = PruneConf { dry_run: None, ..conf };
+        prune(&dir, &dir, &produced, &written, &conf).unwrap();
+        assert!(!dir.join("old.html").exists());
+        assert!(!dir.join("2018").exists());
+        assert!(dir.join("index.html").exists());
+        assert!(dir.join("keep").join("me.html").exists());
+        assert!(dir.join("src").join("a.md").exists());
+        assert!(dir.join("docs").join("README.md").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use post::Post;
+use std::collections::BTreeMap;
+
+// Articles grouped by the directories under the source directory
+// they are found in. An article also belongs to all the sections
+// above its own, so `notes` lists what's in `notes/rust` as well.
+#[derive(Debug)]
+pub struct Sections {
+    sections: BTreeMap<String, Vec<usize>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Section<'a> {
+    pub name: &'a str,
+    pub url: String,
+    pub articles: Vec<&'a Post>,
+}
+
+impl Sections {
+    pub fn from_articles(articles: &[Post]) -> Self {
+        let mut sections = BTreeMap::new();
+        for (i, p) in articles.iter().enumerate() {
+            let mut name = String::new();
+            for dir in p.section.split('/').filter(|d| !d.is_empty()) {
+                if !name.is_empty() {
+                    name.push('/');
+                }
+                name += dir;
+                sections.entry(name.clone()).or_insert_with(Vec::new).push(i);
+            }
+        }
+        Sections { sections }
+    }
+
+    pub fn sections<'a>(&'a self, articles: &'a [Post]) -> Vec<Section<'a>> {
+        self.sections.iter()
+            .map(|(name, indices)| Section {
+                name,
+                url: format!("/{}/{}", name, ::INDEX_FILE),
+                articles: indices.iter().map(|&i| &articles[i]).collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_section(section: &str) -> Post {
+        let mut p = Post::new();
+        p.section = section.to_string();
+        p
+    }
+
+    #[test]
+    fn test_sections() {
+        let articles = vec![in_section("notes/rust"), in_section("notes"), in_section("")];
+        let sections = Sections::from_articles(&articles);
+        let sections = sections.sections(&articles);
+
+        assert!(sections.len() == 2);
+        assert!(sections[0].name == "notes");
+        assert!(sections[0].url == "/notes/index.html");
+        assert!(sections[0].articles.len() == 2);
+        assert!(sections[1].name == "notes/rust");
+        assert!(sections[1].articles.len() == 1);
+    }
+}
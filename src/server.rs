@@ -1,41 +1,455 @@
+use chrono::{DateTime, Utc};
 use conf::Conf;
 use error::{Error, Result};
+use serde_json;
+use site::Site;
+use std::env;
 use std::fs::File;
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use watch::{self, Reload};
 
-fn resp_with_status(req: Request, code: u16) -> Result<()> {
-    println!("{} - {} {}", code, req.method().as_str(), req.url());
-    req.respond(Response::empty(StatusCode(code)))
+const RELOAD_URL: &str = "/__izzet/reload";
+const RELOAD_TIMEOUT_SECS: u64 = 30;
+const NOT_FOUND_HTML: &str = "<html><body>Not Found</body></html>";
+
+// Injected into every HTML page in watch mode. It long-polls the server
+// and reloads the page after a successful build, or shows the error of
+// a failed build in an overlay.
+const RELOAD_SCRIPT: &str = "
+<script>
+(function() {
+  var version = __VERSION__;
+  function overlay(error) {
+    var div = document.getElementById('__izzet_error');
+    if (!div) {
+      div = document.createElement('pre');
+      div.id = '__izzet_error';
+      div.style.cssText = 'position:fixed;top:0;left:0;right:0;bottom:0;margin:0;' +
+        'padding:2em;overflow:auto;z-index:99999;background:rgba(0,0,0,0.85);' +
+        'color:#ff6b6b;font:14px monospace;white-space:pre-wrap';
+      document.body.appendChild(div);
+    }
+    div.textContent = 'izzet build failed:\\n\\n' + error;
+  }
+  function poll() {
+    var xhr = new XMLHttpRequest();
+    xhr.open('GET', '__RELOAD_URL__?version=' + version);
+    xhr.onload = function() {
+      var build = JSON.parse(xhr.responseText);
+      if (build.version === version) return poll();
+      if (!build.error) return location.reload();
+      version = build.version;
+      overlay(build.error);
+      poll();
+    };
+    xhr.onerror = function() { setTimeout(poll, 1000); };
+    xhr.send();
+  }
+  var error = __ERROR__;
+  if (error) overlay(error);
+  poll();
+})();
+</script>
+";
+
+fn header(s: &str) -> Result<Header> {
+    Header::from_str(s).map_err(|_| Error::new("error setting HTTP header".to_string()))
+}
+
+fn req_header<'a>(req: &'a Request, field: &'static str) -> Option<&'a str> {
+    req.headers().iter()
+        .find(|h| h.field.equiv(field))
+        .map(|h| h.value.as_str())
+}
+
+fn send<R: Read>(req: Request, resp: Response<R>) -> Result<()> {
+    println!("{} - {} {}", resp.status_code().0, req.method().as_str(), req.url());
+    req.respond(resp)
        .map_err(|e| Error::new(format!("fail to respond: {}", e)))
 }
 
-pub fn forever<P: AsRef<Path>>(dir: P, conf: Conf) -> Result<()> {
+fn resp_with_status(req: Request, code: u16) -> Result<()> {
+    send(req, Response::empty(StatusCode(code)))
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        }
+        else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// Map the request URL to an existing file under `root`, or return the
+// status code to respond with if there isn't one that may be served.
+fn resolve(root: &Path, url: &str) -> ::std::result::Result<PathBuf, u16> {
+    let url = url.split(['?', '#']).next().unwrap_or("");
+    let url = percent_decode(url).ok_or(400u16)?;
+    if url.contains('\0') {
+        return Err(400);
+    }
+
+    // resolve `..` before touching the file system so that paths
+    // outside the root are refused even if they do not exist
+    let mut rel = PathBuf::new();
+    for c in Path::new(url.trim_start_matches('/')).components() {
+        match c {
+            Component::Normal(c) => rel.push(c),
+            Component::CurDir => {},
+            Component::ParentDir => if !rel.pop() {
+                return Err(403);
+            },
+            Component::RootDir | Component::Prefix(_) => return Err(403),
+        }
+    }
+
+    let root = root.canonicalize().map_err(|_| 404u16)?;
+    let mut path = root.join(rel);
+    if path.is_dir() {
+        path = path.join(::INDEX_FILE);
+    }
+    // symbolic links may still lead elsewhere
+    let path = path.canonicalize().map_err(|_| 404u16)?;
+    if !path.starts_with(&root) {
+        return Err(403);
+    }
+    Ok(path)
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css"          => "text/css; charset=utf-8",
+        "js" | "mjs"   => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "xml"          => "application/xml",
+        "txt" | "md"   => "text/plain; charset=utf-8",
+        "svg"          => "image/svg+xml",
+        "png"          => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif"          => "image/gif",
+        "webp"         => "image/webp",
+        "avif"         => "image/avif",
+        "ico"          => "image/x-icon",
+        "woff"         => "font/woff",
+        "woff2"        => "font/woff2",
+        "ttf"          => "font/ttf",
+        "otf"          => "font/otf",
+        "eot"          => "application/vnd.ms-fontobject",
+        "mp3"          => "audio/mpeg",
+        "ogg"          => "audio/ogg",
+        "wav"          => "audio/wav",
+        "mp4"          => "video/mp4",
+        "webm"         => "video/webm",
+        "pdf"          => "application/pdf",
+        "wasm"         => "application/wasm",
+        "zip"          => "application/zip",
+        _              => "application/octet-stream",
+    }
+}
+
+fn etag(len: u64, mtime: SystemTime) -> String {
+    let t = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("\"{:x}-{:x}-{:x}\"", len, t.as_secs(), t.subsec_nanos())
+}
+
+fn http_date(t: SystemTime) -> String {
+    DateTime::<Utc>::from(t).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+// Whether the client's cached copy is still good, according to
+// `If-None-Match` or, in its absence, `If-Modified-Since`.
+fn is_not_modified(req: &Request, etag: &str, mtime: SystemTime) -> bool {
+    if let Some(tags) = req_header(req, "If-None-Match") {
+        return tags.split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == etag || t == "*");
+    }
+    req_header(req, "If-Modified-Since")
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        .map(|since| DateTime::<Utc>::from(mtime).timestamp() <= since.timestamp())
+        .unwrap_or(false)
+}
+
+#[derive(Debug, PartialEq)]
+enum Range {
+    // anything other than a single byte range, which is ignored
+    Whole,
+    // the first and last byte positions
+    Bytes(u64, u64),
+    Unsatisfiable,
+}
+
+// Parse the `Range` header of a file of `len` bytes.
+fn parse_range(range: &str, len: u64) -> Range {
+    let spec = match range.trim().strip_prefix("bytes=") {
+        Some(s) if !s.contains(',') => s,
+        _ => return Range::Whole,
+    };
+    let (first, last) = match spec.find('-') {
+        Some(i) => (spec[..i].trim(), spec[i + 1..].trim()),
+        None => return Range::Whole,
+    };
+
+    let (first, last) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        // bytes=a-b
+        (Ok(f), Ok(l)) if f <= l => (f, l.min(len.saturating_sub(1))),
+        // bytes=a-
+        (Ok(f), Err(_)) if last.is_empty() => (f, len.saturating_sub(1)),
+        // bytes=-n, the last n bytes
+        (Err(_), Ok(n)) if first.is_empty() && n > 0 => (len.saturating_sub(n), len.saturating_sub(1)),
+        (Err(_), Ok(_)) if first.is_empty() => return Range::Unsatisfiable,
+        _ => return Range::Whole,
+    };
+    if first >= len {
+        return Range::Unsatisfiable;
+    }
+    Range::Bytes(first, last)
+}
+
+fn respond_file(req: Request, mut f: File, path: &Path) -> Result<()> {
+    let meta = f.metadata()?;
+    let len = meta.len();
+    let mtime = meta.modified()?;
+    let etag = etag(len, mtime);
+
+    let mut headers = vec![
+        header(&format!("ETag: {}", etag))?,
+        header(&format!("Last-Modified: {}", http_date(mtime)))?,
+        header("Accept-Ranges: bytes")?,
+        // always revalidate so that changes show up at once
+        header("Cache-Control: no-cache")?,
+    ];
+
+    if is_not_modified(&req, &etag, mtime) {
+        return send(req, Response::new(StatusCode(304), headers, io::empty(), None, None));
+    }
+
+    headers.push(header(&format!("Content-Type: {}", mime_type(path)))?);
+
+    match req_header(&req, "Range").map(|r| parse_range(r, len)) {
+        Some(Range::Unsatisfiable) => {
+            headers.push(header(&format!("Content-Range: bytes */{}", len))?);
+            send(req, Response::new(StatusCode(416), headers, io::empty(), None, None))
+        },
+        Some(Range::Bytes(first, last)) => {
+            let n = last - first + 1;
+            f.seek(SeekFrom::Start(first))?;
+            headers.push(header(&format!("Content-Range: bytes {}-{}/{}", first, last, len))?);
+            send(req, Response::new(StatusCode(206), headers, f.take(n), Some(n as usize), None))
+        },
+        _ => send(req, Response::new(StatusCode(200), headers, f, Some(len as usize), None)),
+    }
+}
+
+fn inject_script(html: &str, reload: &Reload) -> Result<String> {
+    let build = reload.current();
+    let script = RELOAD_SCRIPT
+        .replace("__VERSION__", &build.version.to_string())
+        .replace("__RELOAD_URL__", RELOAD_URL)
+        // make sure the error message does not close the script tag
+        .replace("__ERROR__", &serde_json::to_string(&build.error)?.replace("</", "<\\/"));
+
+    Ok(match html.rfind("</body>") {
+        Some(i) => format!("{}{}{}", &html[..i], script, &html[i..]),
+        None => format!("{}{}", html, script),
+    })
+}
+
+// Pages served in watch mode are never cached, as the injected script
+// changes with every build even if the page itself does not.
+fn respond_html(req: Request, html: &str, code: u16, reload: &Reload) -> Result<()> {
+    let resp = Response::from_string(inject_script(html, reload)?)
+        .with_status_code(code)
+        .with_header(header("Content-Type: text/html; charset=utf-8")?)
+        .with_header(header("Cache-Control: no-cache,no-store,must-revalidate")?);
+    send(req, resp)
+}
+
+fn wait_reload(req: Request, reload: Arc<Reload>) {
+    let version = req.url()
+        .split("version=")
+        .nth(1)
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    thread::spawn(move || {
+        let build = reload.wait(version, Duration::from_secs(RELOAD_TIMEOUT_SECS));
+        let resp = serde_json::to_string(&build).map_err(Error::from)
+            .and_then(|json| Ok(Response::from_string(json)
+                                .with_header(header("Content-Type: application/json")?)))
+            .and_then(|resp| req.respond(resp).map_err(Error::from));
+        if let Err(e) = resp {
+            eprintln!("fail to notify reload: {}", e);
+        }
+    });
+}
+
+fn serve(dir: &Path, conf: &Conf, reload: Option<Arc<Reload>>) -> Result<()> {
     let server = Server::http(("0.0.0.0", conf.port.unwrap_or(::DEFAULT_PORT)))
-        .map_err(|e| Error::new(e.description().to_string()))?;
+        .map_err(|e| Error::new(e.to_string()))?;
 
     loop {
         let req = server.recv()?;
-        if req.method() != &Method::Get {
+        if req.method() != &Method::Get && req.method() != &Method::Head {
             resp_with_status(req, 501)?;
             continue;
         }
 
-        // skip the leading slash
-        let mut path = dir.as_ref().join(&req.url()[1..]);
-        if path.is_dir() {
-            path = path.join(::INDEX_FILE);
+        if let Some(ref reload) = reload {
+            if req.url().starts_with(RELOAD_URL) {
+                wait_reload(req, reload.clone());
+                continue;
+            }
         }
-        match File::open(&path) {
-            Err(_) => resp_with_status(req, 404)?,
-            Ok(f) => {
-                println!("200 - {} {}", req.method().as_str(), req.url());
-                let resp = Response::from_file(f)
-                    .with_header(Header::from_str("Cache-Control: no-cache,no-store,must-revalidate")
-                                 .map_err(|_| Error::new("error setting HTTP header".to_string()))?);
-                req.respond(resp)?;
+
+        let path = match (resolve(dir, req.url()), reload.as_ref()) {
+            (Ok(path), _) => path,
+            // keep waiting for a build that creates the page
+            (Err(404), Some(reload)) => {
+                respond_html(req, NOT_FOUND_HTML, 404, reload)?;
+                continue;
+            },
+            (Err(code), _) => {
+                resp_with_status(req, code)?;
+                continue;
             },
+        };
+        let is_html = path.extension().map(|e| e == "html").unwrap_or(false);
+
+        match (File::open(&path), reload.as_ref()) {
+            (Err(_), Some(reload)) => respond_html(req, NOT_FOUND_HTML, 404, reload)?,
+            (Err(_), None) => resp_with_status(req, 404)?,
+            (Ok(mut f), Some(reload)) if is_html => {
+                let mut html = String::new();
+                f.read_to_string(&mut html)?;
+                respond_html(req, &html, 200, reload)?;
+            },
+            (Ok(f), _) => respond_file(req, f, &path)?,
         }
     }
 }
+
+pub fn forever<P: AsRef<Path>>(dir: P, conf: Conf) -> Result<()> {
+    serve(dir.as_ref(), &conf, None)
+}
+
+// Re-read the configuration, keeping what was given on the command line,
+// and build the site again, telling the browsers about the result.
+fn rebuild(conf_path: &Path, conf: &Conf, reload: &Reload) {
+    let result = Conf::from_file(conf_path).and_then(|mut c| {
+        c.in_dir = conf.in_dir.clone();
+        c.out_dir = conf.out_dir.clone();
+        c.force = Some(true);
+        c.drafts = conf.drafts.or(c.drafts);
+        c.full = conf.full.or(c.full);
+        c.prune = conf.prune.clone().or(c.prune);
+        Site::collect(&c).and_then(|s| s.generate())
+    });
+    match result {
+        Ok(_) => println!("site rebuilt"),
+        Err(ref e) => eprintln!("error building site: {}", e),
+    }
+    reload.done(result);
+}
+
+// Serve `dir` like `forever`, but also build the site into it and rebuild
+// whenever the sources, the themes, the static files or the configuration change.
+pub fn watch<P: AsRef<Path>>(dir: P, conf_path: PathBuf, mut conf: Conf) -> Result<()> {
+    let in_dir = conf.in_dir.as_ref()
+        .map(PathBuf::from)
+        .unwrap_or(env::current_dir()?);
+    conf.out_dir = dir.as_ref().to_str().map(String::from);
+
+    let reload = Arc::new(Reload::new());
+    rebuild(&conf_path, &conf, &reload);
+
+    let paths = vec![in_dir.join(::SRC_DIR), in_dir.join(::THEME_DIR), in_dir.join(::THEMES_DIR),
+                     in_dir.join(::STATIC_DIR), in_dir.join(::DATA_DIR), conf_path.clone()];
+    let (c, r) = (conf.clone(), reload.clone());
+    thread::spawn(move || watch::on_change(paths, || rebuild(&conf_path, &c, &r)));
+
+    serve(dir.as_ref(), &conf, Some(reload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::files;
+    use ::std::{env, fs};
+
+    #[test]
+    fn test_mime_type() {
+        assert!(mime_type(Path::new("a/b.SVG")) == "image/svg+xml");
+        assert!(mime_type(Path::new("f.woff2")) == "font/woff2");
+        assert!(mime_type(Path::new("noext")) == "application/octet-stream");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert!(parse_range("bytes=0-9", 100) == Range::Bytes(0, 9));
+        assert!(parse_range("bytes=90-200", 100) == Range::Bytes(90, 99));
+        assert!(parse_range("bytes=50-", 100) == Range::Bytes(50, 99));
+        assert!(parse_range("bytes=-10", 100) == Range::Bytes(90, 99));
+        assert!(parse_range("bytes=-200", 100) == Range::Bytes(0, 99));
+        assert!(parse_range("bytes=100-", 100) == Range::Unsatisfiable);
+        assert!(parse_range("bytes=-0", 100) == Range::Unsatisfiable);
+        assert!(parse_range("bytes=0-1,5-6", 100) == Range::Whole);
+        assert!(parse_range("items=0-1", 100) == Range::Whole);
+        assert!(parse_range("bytes=5-1", 100) == Range::Whole);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = env::temp_dir().join("resolve");
+        let root = dir.join("root");
+        files::fwrite(root.join("a b.html"), b"", true).unwrap();
+        files::fwrite(root.join("sub").join(::INDEX_FILE), b"", true).unwrap();
+        files::fwrite(dir.join("secret"), b"", true).unwrap();
+        let root_canon = root.canonicalize().unwrap();
+
+        assert!(resolve(&root, "/a%20b.html?x=1#top") == Ok(root_canon.join("a b.html")));
+        assert!(resolve(&root, "/sub/") == Ok(root_canon.join("sub").join(::INDEX_FILE)));
+        assert!(resolve(&root, "/sub/../a%20b.html") == Ok(root_canon.join("a b.html")));
+        assert!(resolve(&root, "/missing.html") == Err(404));
+
+        assert!(resolve(&root, "/../secret") == Err(403));
+        assert!(resolve(&root, "/%2e%2e/secret") == Err(403));
+        assert!(resolve(&root, "/sub/..%2f..%2fsecret") == Err(403));
+        assert!(resolve(&root, "/../no/such/file") == Err(403));
+        assert!(resolve(&root, "//etc/passwd") == Err(404));
+
+        assert!(resolve(&root, "/a%00.html") == Err(400));
+        assert!(resolve(&root, "/a%zz.html") == Err(400));
+        assert!(resolve(&root, "/%ff.html") == Err(400));
+
+        #[cfg(unix)]
+        {
+            ::std::os::unix::fs::symlink(dir.join("secret"), root.join("link")).unwrap();
+            assert!(resolve(&root, "/link") == Err(403));
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
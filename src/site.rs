@@ -1,17 +1,200 @@
+use cache::{self, Cache, Hasher, Source, Writer};
 use conf::Conf;
-use error::{Result, ResultContext};
+use data;
+use error::{Error, Result, ResultContext};
+use feed::Feed;
 use files;
+use highlight::{self, HighlightConf};
+use nav::Nav;
+use rayon::prelude::*;
+use section::{Section, Sections};
+use serde_json;
+use sitemap::Sitemap;
+use taxonomy::{Taxonomy, Term};
+use template;
 use tera::{Tera, Context};
+use theme::{self, Theme};
 use post::{Post, PostKind};
-use std::{env, fs};
-use std::path::PathBuf;
+use prune;
+use std::env;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+// title of a site configuring none, even through its theme
+const DEFAULT_TITLE: &str = "Default title";
+
+#[derive(Serialize, Debug)]
+pub struct Paginator<'a> {
+    // pages are numbered from 1
+    pub current: usize,
+    pub total: usize,
+    pub url: String,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+    pub items: &'a [Post],
+}
+
+impl<'a> Paginator<'a> {
+    // Split `items` into pages of `per_page` items, the first of which is at
+    // `first_url` and the n-th of which is at `nth_url(n)`. There is always at
+    // least one page even if there's nothing to show.
+    pub fn paginate(items: &'a [Post], per_page: usize, first_url: &str,
+                    nth_url: fn(usize) -> String) -> Vec<Self> {
+        let per_page = per_page.max(1);
+        let total = items.len().div_ceil(per_page).max(1);
+        let url = |n: usize| if n == 1 { first_url.to_string() } else { nth_url(n) };
+
+        (1..total + 1)
+            .map(|n| Paginator {
+                current: n,
+                total,
+                url: url(n),
+                prev: if n > 1 { Some(url(n - 1)) } else { None },
+                next: if n < total { Some(url(n + 1)) } else { None },
+                items: &items[((n - 1) * per_page).min(items.len())
+                              ..(n * per_page).min(items.len())],
+            })
+            .collect()
+    }
+}
+
+// The context of one page: the site context shared by all pages
+// and what is particular to that page, which wins on conflicts.
+#[derive(Serialize)]
+struct PageContext<'a> {
+    #[serde(flatten)]
+    site: &'a Context,
+    #[serde(flatten)]
+    page: Context,
+}
+
+// What a file of the site is generated from.
+enum Content<'a> {
+    Post(&'a Post, Nav<'a>),
+    // a page of the listing rendered by the template
+    Listing(&'static str, Paginator<'a>),
+    Taxonomy(&'a Taxonomy),
+    Term(&'a Taxonomy, Term<'a>),
+    Section(Section<'a>),
+    // the Atom feed of the site, or the RSS one
+    Feed { rss: bool },
+    TagFeed(Term<'a>),
+    Sitemap,
+    Robots,
+    Highlight(&'a HighlightConf),
+    // copied rather than written
+    Static(PathBuf),
+}
+
+impl<'a> Content<'a> {
+    // For telling which of them end up at the same URL.
+    fn describe(&self) -> String {
+        match *self {
+            Content::Post(p, _) => format!("{:?}", p.path),
+            Content::Listing(template, ref page) => {
+                format!("page {} of {}", page.current, template)
+            },
+            Content::Taxonomy(tax) => format!("the {} page", tax.name),
+            Content::Term(tax, ref term) => format!("the page of {} {:?}", tax.name, term.name),
+            Content::Section(ref section) => format!("the page of section {:?}", section.name),
+            Content::Feed { rss } => format!("the {} feed", if rss { "RSS" } else { "Atom" }),
+            Content::TagFeed(ref term) => format!("the feed of tag {:?}", term.name),
+            Content::Sitemap => "the sitemap".to_string(),
+            Content::Robots => "robots.txt".to_string(),
+            Content::Highlight(_) => "the highlight stylesheet".to_string(),
+            Content::Static(ref path) => format!("{:?}", path),
+        }
+    }
+}
+
+// A file the site is generated to.
+struct Output<'a> {
+    url: String,
+    content: Content<'a>,
+}
+
+// Wait for all jobs run on the worker pool and report the error of the
+// first one in order, so that it doesn't depend on how they are scheduled.
+fn first_error<I>(jobs: I) -> Result<()>
+        where I: IndexedParallelIterator<Item = Result<()>>
+{
+    jobs.collect::<Vec<_>>().into_iter().collect()
+}
+
+// Path of `path` relative to `dir` with `/` between the components.
+fn relative_name(path: &Path, dir: &Path) -> Option<String> {
+    path.strip_prefix(dir).ok()
+        .map(|p| p.components()
+                  .map(|c| c.as_os_str().to_string_lossy())
+                  .collect::<Vec<_>>()
+                  .join("/"))
+}
+
+// The templates under each of `dirs` named by their paths relative to it,
+// where a template in one of them hides those of the same name in the later ones.
+fn load_templates(dirs: &[PathBuf]) -> Result<Tera> {
+    let mut templates = BTreeMap::new();
+    for dir in dirs.iter().rev().filter(|d| d.is_dir()) {
+        for path in files::walk(dir)? {
+            templates.insert(relative_name(&path, dir).unwrap(), path);
+        }
+    }
+
+    let mut tera = Tera::default();
+    tera.add_template_files(templates.iter().map(|(name, p)| (p, Some(name.as_str()))).collect())
+        .context("compile templates fails".to_string())?;
+    Ok(tera)
+}
+
+fn index_url(n: usize) -> String {
+    format!("/page/{}/{}", n, ::INDEX_FILE)
+}
+
+fn archive_url(n: usize) -> String {
+    format!("/archive/{}.html", n)
+}
+
+fn tag_feed_url(taxonomy: &str, slug: &str) -> String {
+    format!("/{}/{}.xml", taxonomy, slug)
+}
+
+// Why `url` can't be where a post is written to, if it can't.
+fn check_url(url: &str) -> ::std::result::Result<(), String> {
+    if !url.starts_with('/') {
+        return Err("it doesn't start with /".to_string());
+    }
+    if url.ends_with('/') {
+        return Err("it doesn't name a file".to_string());
+    }
+    if let Some(c) = url.chars().find(|&c| c.is_whitespace() || c.is_control()
+                                      || "\"#<>?\\^`{|}".contains(c)) {
+        return Err(format!("{:?} is not allowed", c));
+    }
+    if url.split('/').any(|c| c == "." || c == "..") {
+        return Err("it has . or .. in it".to_string());
+    }
+    Ok(())
+}
 
 #[derive(Debug)]
 pub struct Site {
+    in_dir: PathBuf,
+    // the configuration of the site on top of that of its themes
+    conf: Conf,
+    themes: Vec<Theme>,
     ctx: Context,
     articles: Vec<Post>,
     pages: Vec<Post>,
+    taxonomies: Vec<Taxonomy>,
+    sections: Sections,
     tera: Tera,
+    // what the last build did and what this one read
+    cache: Cache,
+    sources: BTreeMap<String, Source>,
+    // hashes of the inputs of a post page except the post itself,
+    // and of the inputs of pages listing the posts
+    meta_key: u64,
+    listing_key: u64,
 }
 
 impl Site {
@@ -20,21 +203,77 @@ impl Site {
             .map(PathBuf::from)
             .unwrap_or(env::current_dir()?);
 
+        let themes = theme::chain(&in_dir, conf.theme.as_deref())?;
+        let mut conf = theme::apply(conf, &themes)?;
+        if conf.title.is_none() {
+            conf.title = Some(DEFAULT_TITLE.to_string());
+        }
+        let conf = &conf;
+
         let mut articles = vec![];
         let mut pages = vec![];
 
-        let template_path = in_dir.join(::THEME_DIR).join("*");
-
-        let mut tera = Tera::new(template_path.to_str().unwrap())
-            .context("compile templates fails".to_string())?;
+        // the templates of the site override those of its themes
+        let template_dirs = Some(in_dir.join(::THEME_DIR)).into_iter()
+            .chain(themes.iter().map(|t| t.templates_dir()))
+            .collect::<Vec<_>>();
+        let mut tera = load_templates(&template_dirs)?;
 
         tera.autoescape_on(vec![]);
 
-        for entry in fs::read_dir(in_dir.join(::SRC_DIR))? {
-            let post = match Post::from_file(&entry?.path())? {
+        // rather than failing only when some code is highlighted
+        if let Some(ref hl) = conf.highlight {
+            hl.theme()?;
+        }
+        if let Some(ref nav) = conf.nav {
+            nav.check()?;
+        }
+
+        let cache = if conf.full.unwrap_or(false) {
+            Cache::default()
+        }
+        else {
+            Cache::load(in_dir.join(::CACHE_FILE))
+        };
+        let conf_hash = cache::hash_conf(conf)?;
+        let mut global = Hasher::new().write_u64(conf_hash);
+        for dir in &template_dirs {
+            global = global.write_u64(cache::hash_dir(dir)?);
+        }
+        let global = global.write_u64(cache::hash_dir(in_dir.join(::DATA_DIR))?).finish();
+
+        let src_dir = in_dir.join(::SRC_DIR);
+        let parsed = files::walk(&src_dir)?
+            .into_par_iter()
+            .map(|path| {
+                // parsing depends on the configuration as well
+                let name = path.to_string_lossy().into_owned();
+                let hash = Hasher::new().write_u64(conf_hash).write(&files::fread(&path)?).finish();
+                let post = match cache.sources.get(&name) {
+                    Some(s) if s.hash == hash => s.post.clone(),
+                    _ => Post::from_file(&path, conf)?,
+                };
+                Ok((path, name, Source { hash, post }))
+            })
+            .collect::<Vec<Result<_>>>();
+
+        let mut sources = BTreeMap::new();
+        for result in parsed {
+            let (path, name, source) = result?;
+            let post = source.post.clone();
+            sources.insert(name, source);
+
+            let mut post = match post {
                 None => continue,
                 Some(p) => p,
             };
+            post.path = path.clone();
+            post.section = path.parent()
+                .and_then(|d| relative_name(d, &src_dir))
+                .unwrap_or_default();
+            if post.draft && !conf.drafts.unwrap_or(false) {
+                continue;
+            }
             match post.kind {
                 PostKind::Article => articles.push(post),
                 PostKind::Page => pages.push(post),
@@ -43,44 +282,312 @@ impl Site {
         articles.sort_by(|x, y| y.ts.cmp(&x.ts));
         pages.sort_by(|x, y| y.ts.cmp(&x.ts));
 
+        // Post pages are only rendered again when the post itself or those it
+        // links to change, or when anything about the other posts changes
+        // except their content.
+        let mut meta_key = Hasher::new().write_u64(global);
+        for p in articles.iter().chain(pages.iter()) {
+            meta_key = meta_key
+                .write(serde_json::to_string(&p.meta)?.as_bytes())
+                .write(p.section.as_bytes())
+                .write(p.summary.as_bytes())
+                .write(&[p.has_more as u8]);
+        }
+        let meta_key = meta_key.finish();
+        let listing_key = sources.values()
+            .fold(Hasher::new().write_u64(global), |h, s| h.write_u64(s.hash))
+            .finish();
+
+        let taxonomies = Taxonomy::from_articles(&articles);
+        let sections = Sections::from_articles(&articles);
+
+        let terms = taxonomies.iter()
+            .map(|t| Ok((t.name.clone(), serde_json::to_value(t.terms(&articles))?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let posts = articles.iter().chain(pages.iter())
+            .map(|p| Ok((relative_name(&p.path, &src_dir).unwrap(), serde_json::to_value(p)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
         let mut ctx = Context::new();
+        ctx.add("data", &data::load(in_dir.join(::DATA_DIR))?);
         ctx.add("articles", &articles);
         ctx.add("pages", &pages);
         ctx.add("conf", &conf);
+        ctx.add("taxonomies", &terms);
+        ctx.add("sections", &sections.sections(&articles));
         if let Some(p) = articles.first() {
             ctx.add("latest_article", p);
         }
 
-        Ok(Site { ctx, articles, pages, tera })
+        template::register(&mut tera, conf, posts, terms);
+
+        let site = Site {
+            in_dir, conf: conf.clone(), themes, ctx, articles, pages, taxonomies, sections,
+            tera, cache, sources, meta_key, listing_key,
+        };
+        site.check_urls()?;
+        Ok(site)
+    }
+
+    // Every file the site is generated to, which is both what `generate` writes
+    // and what `check_urls` makes sure doesn't overwrite each other.
+    fn outputs(&self) -> Result<Vec<Output<'_>>> {
+        let conf = &self.conf;
+        let nav_conf = conf.nav.clone().unwrap_or_default();
+        let mut outputs = self.articles.par_iter().enumerate()
+            .map(|(i, p)| (p, Nav::new(&nav_conf, &self.articles, &self.taxonomies, i)))
+            .chain(self.pages.par_iter().map(|p| (p, Nav::default())))
+            .map(|(p, nav)| Ok(Output { url: p.url()?, content: Content::Post(p, nav) }))
+            .collect::<Result<Vec<_>>>()?;
+
+        let listing = |template: &'static str, per_page, nth_url| {
+            self.paginate(template, per_page, nth_url).into_iter()
+                .map(move |page| Output { url: page.url.clone(),
+                                          content: Content::Listing(template, page) })
+        };
+        outputs.extend(listing(::INDEX_FILE, conf.paginate, index_url));
+        outputs.extend(listing(::ARCHIVE_FILE, conf.archive_paginate, archive_url));
+
+        // these pages are only rendered by themes which have templates for them
+        let has = |template| self.tera.get_template(template).is_ok();
+        for tax in &self.taxonomies {
+            if has(::TAXONOMY_FILE) {
+                outputs.push(Output { url: tax.url(), content: Content::Taxonomy(tax) });
+            }
+            if has(::TERM_FILE) {
+                for term in tax.terms(&self.articles) {
+                    outputs.push(Output { url: term.url.clone(),
+                                          content: Content::Term(tax, term) });
+                }
+            }
+        }
+        if has(::SECTION_FILE) {
+            for section in self.sections.sections(&self.articles) {
+                outputs.push(Output { url: section.url.clone(),
+                                      content: Content::Section(section) });
+            }
+        }
+
+        if let Some(ref feed) = conf.feed {
+            outputs.push(Output { url: feed.path(), content: Content::Feed { rss: false } });
+            if let Some(ref path) = feed.rss_path {
+                outputs.push(Output { url: path.clone(), content: Content::Feed { rss: true } });
+            }
+            if feed.per_tag.unwrap_or(false) {
+                for tax in self.taxonomies.iter().filter(|t| t.name == "tags") {
+                    for term in tax.terms(&self.articles) {
+                        outputs.push(Output { url: tag_feed_url(&tax.name, term.slug),
+                                              content: Content::TagFeed(term) });
+                    }
+                }
+            }
+        }
+        if let Some(ref sitemap) = conf.sitemap {
+            outputs.push(Output { url: sitemap.path(), content: Content::Sitemap });
+            if sitemap.robots() {
+                outputs.push(Output { url: ::ROBOTS_FILE.to_string(), content: Content::Robots });
+            }
+        }
+        if let Some(ref hl) = conf.highlight {
+            if hl.css() {
+                outputs.push(Output { url: hl.stylesheet(), content: Content::Highlight(hl) });
+            }
+        }
+
+        for (name, path) in self.static_files()? {
+            outputs.push(Output { url: format!("/{}", name), content: Content::Static(path) });
+        }
+        Ok(outputs)
     }
 
-    pub fn generate(&self, conf: &Conf) -> Result<()> {
+    // Report every post whose URL isn't valid, and every URL more than one
+    // page or file would be written to, with all that would be written there.
+    fn check_urls(&self) -> Result<()> {
+        let mut errors = vec![];
+        for p in self.articles.iter().chain(self.pages.iter()) {
+            let url = p.url()?;
+            if let Err(e) = check_url(&url) {
+                errors.push(format!("invalid URL {:?} of {:?}: {}", url, p.path, e));
+            }
+        }
+
+        let mut by_url = BTreeMap::new();
+        for output in self.outputs()? {
+            by_url.entry(format!("/{}", output.url.trim_start_matches('/')))
+                .or_insert_with(Vec::new)
+                .push(output.content.describe());
+        }
+        for (url, from) in by_url.into_iter().filter(|(_, f)| f.len() > 1) {
+            errors.push(format!("{} would be generated from each of {}", url, from.join(", ")));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(Error::new(format!("conflicting or invalid URLs:\n{}", errors.join("\n"))))
+        }
+    }
+
+    pub fn generate(&self) -> Result<()> {
+        let conf = &self.conf;
         let out_dir = conf.out_dir.as_ref()
             .map(PathBuf::from)
             .unwrap_or(env::current_dir()?);
+        let w = Writer::new(&out_dir, conf.force.unwrap_or(false), Some(&self.cache));
 
-        for p in self.articles.iter().chain(self.pages.iter()) {
-            // skip the leading slash to make the output path correct
-            let url = &p.url()?[1..];
+        let outputs = self.outputs()?;
+        first_error(outputs.par_iter().map(|output| self.write(&w, output)))?;
+        files::copy_trees(&self.static_dirs(), &out_dir, conf.force.unwrap_or(false))?;
+
+        // what earlier builds wrote, even with `--full`, which leaves `self.cache` empty
+        let last = Cache::load(self.in_dir.join(::CACHE_FILE));
+        let mut written = if last.out_dir == out_dir { last.written } else { BTreeSet::new() };
+        let produced = outputs.iter()
+            .map(|o| o.url.trim_start_matches('/').to_string())
+            .collect::<BTreeSet<_>>();
+        if let Some(prune_conf) = conf.prune.as_ref().filter(|p| p.enabled()) {
+            prune::prune(&out_dir, &self.in_dir, &produced, &written, prune_conf)?;
+        }
+        written.retain(|f| out_dir.join(f).exists());
+        written.extend(produced);
+
+        Cache {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            out_dir: out_dir.clone(),
+            sources: self.sources.clone(),
+            outputs: w.outputs(),
+            written,
+        }.save(self.in_dir.join(::CACHE_FILE))
+    }
 
-            let mut ctx = Context::new();
-            ctx.extend(self.ctx.clone());
-            ctx.add("post", p);
+    // Write `output` unless it is a static file, which is copied instead.
+    fn write(&self, w: &Writer, output: &Output) -> Result<()> {
+        let conf = &self.conf;
+        let url = &output.url;
+        let key = self.listing_key;
+        match output.content {
+            Content::Post(p, ref nav) => {
+                // the page shows the posts it links to as well
+                let key = Some(p).into_iter()
+                    .chain(nav.prev).chain(nav.next).chain(nav.related.iter().cloned())
+                    .fold(Hasher::new().write_u64(self.meta_key),
+                          |h, q| h.write_u64(self.sources[&*q.path.to_string_lossy()].hash))
+                    .finish();
+                self.render_to(w, &self.post_template(p)?, url, key, |ctx| {
+                    ctx.add("post", p);
+                    ctx.add("prev", &nav.prev);
+                    ctx.add("next", &nav.next);
+                    ctx.add("related", &nav.related);
+                })
+            },
+            Content::Listing(template, ref paginator) => {
+                self.render_to(w, template, url, key, |ctx| ctx.add("paginator", paginator))
+            },
+            Content::Taxonomy(tax) => {
+                let terms = tax.terms(&self.articles);
+                self.render_to(w, ::TAXONOMY_FILE, url, key, |ctx| {
+                    ctx.add("taxonomy", &tax.name);
+                    ctx.add("terms", &terms);
+                })
+            },
+            Content::Term(tax, ref term) => {
+                self.render_to(w, ::TERM_FILE, url, key, |ctx| {
+                    ctx.add("taxonomy", &tax.name);
+                    ctx.add("term", term);
+                })
+            },
+            Content::Section(ref section) => {
+                self.render_to(w, ::SECTION_FILE, url, key, |ctx| ctx.add("section", section))
+            },
+            Content::Feed { rss } => {
+                let title = conf.title.clone().unwrap_or_default();
+                let feed = Feed::new(conf, title, "/", &self.articles)?;
+                w.write(url, key, || if rss { feed.rss() } else { feed.atom(url) })
+            },
+            Content::TagFeed(ref term) => {
+                let title = format!("{} - {}", conf.title.clone().unwrap_or_default(), term.name);
+                let feed = Feed::new(conf, title, &term.url, term.articles.iter().cloned())?;
+                w.write(url, key, || feed.atom(url))
+            },
+            Content::Sitemap => {
+                let sitemap = Sitemap::new(conf, self.articles.iter().chain(self.pages.iter()))?;
+                w.write(url, key, || sitemap.xml())
+            },
+            Content::Robots => {
+                let sitemap = Sitemap::new(conf, self.articles.iter().chain(self.pages.iter()))?;
+                w.write(url, key, || Ok(sitemap.robots().unwrap_or_default()))
+            },
+            Content::Highlight(hl) => w.write(url, key, || highlight::stylesheet(hl)),
+            Content::Static(_) => Ok(()),
+        }
+    }
+
+    // The static files of the site override those of its themes.
+    fn static_dirs(&self) -> Vec<PathBuf> {
+        Some(self.in_dir.join(::STATIC_DIR)).into_iter()
+            .chain(self.themes.iter().map(|t| t.static_dir()))
+            .filter(|d| d.is_dir())
+            .collect()
+    }
 
-            let rendered = self.tera.render(::POST_FILE, &ctx)
-                .context(format!("fail to generate {}", url))?;
-            files::fwrite(out_dir.join(url), rendered.as_bytes(),
-                          conf.force.unwrap_or(false))?;
+    // The static files copied to the output directory by their paths
+    // relative to it. Only the first of those of the same name is copied.
+    fn static_files(&self) -> Result<BTreeMap<String, PathBuf>> {
+        let mut copied = BTreeMap::new();
+        for dir in self.static_dirs() {
+            for path in files::walk(&dir)? {
+                copied.entry(relative_name(&path, &dir).unwrap()).or_insert(path);
+            }
         }
+        Ok(copied)
+    }
 
-        for f in &[::INDEX_FILE, ::ARCHIVE_FILE] {
-            self.tera.render(f, &self.ctx)
-                .context(format!("fail to generate {}", f))
-                .and_then(|rendered| files::fwrite(out_dir.join(f), rendered.as_bytes(),
-                                                   conf.force.unwrap_or(false)))?;
+    // The template given by the post, or the first existing one of
+    // `<kind>.html`, `<section>/post.html` for the section of the post
+    // and then its ancestors, and `post.html`.
+    fn post_template(&self, p: &Post) -> Result<String> {
+        if let Some(ref t) = p.template {
+            return match self.tera.get_template(t) {
+                Ok(_) => Ok(t.to_string()),
+                Err(_) => Err(Error::new(format!("template {:?} of {:?} not found", t, p.path))),
+            };
         }
 
-        Ok(())
+        let mut candidates = vec![match p.kind {
+            PostKind::Article => "article.html".to_string(),
+            PostKind::Page => "page.html".to_string(),
+        }];
+        let mut section = p.section.as_str();
+        while !section.is_empty() {
+            candidates.push(format!("{}/{}", section, ::POST_FILE));
+            section = section.rfind('/').map(|i| &section[..i]).unwrap_or("");
+        }
+
+        Ok(candidates.into_iter()
+            .find(|t| self.tera.get_template(t).is_ok())
+            .unwrap_or(::POST_FILE.to_string()))
+    }
+
+    fn paginate(&self, template: &str, per_page: Option<usize>,
+                nth_url: fn(usize) -> String) -> Vec<Paginator<'_>> {
+        let first_url = format!("/{}", template);
+        let per_page = per_page.unwrap_or(self.articles.len());
+        Paginator::paginate(&self.articles, per_page, &first_url, nth_url)
+    }
+
+    // Render `template` to `url` with the site context
+    // and whatever `add` puts into it.
+    fn render_to<F>(&self, w: &Writer, template: &str, url: &str, key: u64,
+                    add: F) -> Result<()>
+            where F: FnOnce(&mut Context)
+    {
+        w.write(url, key, || {
+            let mut page = Context::new();
+            add(&mut page);
+            self.tera.render(template, &PageContext { site: &self.ctx, page })
+                .context(format!("fail to generate {}", url))
+        })
     }
 }
 
@@ -88,7 +595,9 @@ impl Site {
 mod tests {
     use super::*;
     use ::std::{env, fs};
+    use ::highlight::HighlightConf;
     use ::post::{create_post, PostKind};
+    use ::prune::PruneConf;
 
     #[test]
     fn test_generate() {
@@ -99,22 +608,401 @@ mod tests {
         c.force = Some(true);
         c.in_dir = Some(dir.to_str().unwrap().to_string());
         c.out_dir = Some(dir.to_str().unwrap().to_string());
+        c.base_url = Some("https://example.com".to_string());
+        c.feed = Some(Default::default());
+        c.sitemap = Some(Default::default());
+        c.highlight = Some(HighlightConf { css: Some(true), ..Default::default() });
 
-        create_post(dir.join(::SRC_DIR).join("a.md"), c.clone(), PostKind::Article).unwrap();
-        create_post(dir.join(::SRC_DIR).join("p.md"), c.clone(), PostKind::Page).unwrap();
+        create_post(dir.join(::SRC_DIR).join("a.md"), c.clone(), PostKind::Article, false).unwrap();
+        create_post(dir.join(::SRC_DIR).join("p.md"), c.clone(), PostKind::Page, false).unwrap();
+        files::fwrite(dir.join(::STATIC_DIR).join("css").join("x.css"), b"", true).unwrap();
+        files::fwrite(dir.join(::DATA_DIR).join("menu.json"), b"[\"home\"]", true).unwrap();
+        let a = dir.join(::SRC_DIR).join("a.md");
+        let src = String::from_utf8(fs::read(&a).unwrap()).unwrap()
+            .replace("tags = []", "tags = [\"Rust\"]");
+        fs::write(&a, src).unwrap();
 
         let site = Site::collect(&c).unwrap();
 
         assert!(site.articles.first().unwrap().link == "a");
         assert!(site.pages.first().unwrap().link == "p");
+        assert!(serde_json::to_value(&site.ctx).unwrap()["data"]["menu"][0] == "home");
 
-        site.generate(&c).unwrap();
+        site.generate().unwrap();
 
         let p = site.articles.first().unwrap();
         assert!(dir.join("p.html").exists());
         assert!(dir.join(p.ts.format("%Y/%m/%d").to_string()).join("a.html").exists());
         assert!(dir.join(::INDEX_FILE).exists());
         assert!(dir.join(::ARCHIVE_FILE).exists());
+        assert!(dir.join("tags").join(::INDEX_FILE).exists());
+        assert!(dir.join("tags").join("rust.html").exists());
+        assert!(dir.join("categories").join(::INDEX_FILE).exists());
+        assert!(dir.join("atom.xml").exists());
+        assert!(dir.join("sitemap.xml").exists());
+        assert!(dir.join(::ROBOTS_FILE).exists());
+        assert!(dir.join("highlight.css").exists());
+        assert!(String::from_utf8(fs::read(dir.join("p.html")).unwrap()).unwrap()
+                .contains("href=\"/highlight.css\""));
+        assert!(dir.join("css").join("x.css").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_optional_templates() {
+        let dir = env::temp_dir().join("site-optional");
+        ::new::create_site(dir.clone(), true).unwrap();
+        for t in &[::TAXONOMY_FILE, ::TERM_FILE, ::SECTION_FILE] {
+            fs::remove_file(dir.join(::THEME_DIR).join(t)).unwrap();
+        }
+
+        let c = Conf {
+            force: Some(true),
+            in_dir: Some(dir.to_str().unwrap().to_string()),
+            out_dir: Some(dir.to_str().unwrap().to_string()),
+            ..Conf::default()
+        };
+
+        let a = dir.join(::SRC_DIR).join("notes").join("a.md");
+        create_post(&a, c.clone(), PostKind::Article, false).unwrap();
+        let src = String::from_utf8(fs::read(&a).unwrap()).unwrap()
+            .replace("tags = []", "tags = [\"Rust\"]");
+        fs::write(&a, src).unwrap();
+
+        let site = Site::collect(&c).unwrap();
+        site.generate().unwrap();
+        assert!(dir.join(::INDEX_FILE).exists());
+        assert!(dir.join(site.articles[0].url().unwrap().trim_start_matches('/')).exists());
+        assert!(!dir.join("tags").exists());
+        assert!(!dir.join("categories").exists());
+        assert!(!dir.join("notes").join(::INDEX_FILE).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sections() {
+        let dir = env::temp_dir().join("sections");
+        ::new::create_site(dir.clone(), true).unwrap();
+
+        let mut c = Conf::default();
+        c.force = Some(true);
+        c.in_dir = Some(dir.to_str().unwrap().to_string());
+        c.out_dir = Some(dir.to_str().unwrap().to_string());
+
+        let src = dir.join(::SRC_DIR).join("notes").join("rust").join("a.md");
+        create_post(&src, c.clone(), PostKind::Article, false).unwrap();
+        let meta = String::from_utf8(fs::read(&src).unwrap()).unwrap()
+            .replace("/{{ year }}/{{ month }}/{{ day }}/", "/{{ section }}/");
+        fs::write(&src, meta).unwrap();
+
+        let site = Site::collect(&c).unwrap();
+        assert!(site.articles[0].section == "notes/rust");
+        assert!(site.articles[0].url().unwrap() == "/notes/rust/a.html");
+
+        site.generate().unwrap();
+        assert!(dir.join("notes").join("rust").join("a.html").exists());
+        assert!(dir.join("notes").join(::INDEX_FILE).exists());
+        assert!(dir.join("notes").join("rust").join(::INDEX_FILE).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_incremental() {
+        let dir = env::temp_dir().join("incremental");
+        ::new::create_site(dir.clone(), true).unwrap();
+
+        let mut c = Conf::default();
+        c.in_dir = Some(dir.to_str().unwrap().to_string());
+        c.out_dir = Some(dir.join("out").to_str().unwrap().to_string());
+        c.force = Some(true);
+        let a = dir.join(::SRC_DIR).join("a.md");
+        create_post(&a, c.clone(), PostKind::Page, false).unwrap();
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(dir.join(::CACHE_FILE).exists());
+
+        // unchanged outputs are left alone, even without force
+        c.force = None;
+        let out = dir.join("out").join("a.html");
+        fs::write(&out, b"tampered").unwrap();
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(fs::read(&out).unwrap() == b"tampered");
+
+        // changed posts are rendered again
+        c.force = Some(true);
+        let src = String::from_utf8(fs::read(&a).unwrap()).unwrap() + "\nmore";
+        fs::write(&a, src).unwrap();
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(String::from_utf8(fs::read(&out).unwrap()).unwrap().contains("more"));
+
+        // as are the pages of the articles next to changed ones
+        let x = dir.join(::SRC_DIR).join("x.md");
+        let y = dir.join(::SRC_DIR).join("y.md");
+        create_post(&x, c.clone(), PostKind::Article, false).unwrap();
+        create_post(&y, c.clone(), PostKind::Article, false).unwrap();
+        let site = Site::collect(&c).unwrap();
+        site.generate().unwrap();
+        let x_out = dir.join("out").join(site.articles.iter()
+            .find(|p| p.link == "x").unwrap()
+            .url().unwrap().trim_start_matches('/'));
+        fs::write(&x_out, b"tampered").unwrap();
+        let src = String::from_utf8(fs::read(&y).unwrap()).unwrap() + "\nmore";
+        fs::write(&y, src).unwrap();
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(fs::read(&x_out).unwrap() != b"tampered");
+
+        // and a full build renders everything
+        fs::write(&out, b"tampered").unwrap();
+        c.full = Some(true);
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(fs::read(&out).unwrap() != b"tampered");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_errors() {
+        let dir = env::temp_dir().join("parallel");
+        ::new::create_site(dir.clone(), true).unwrap();
+
+        let mut c = Conf::default();
+        c.in_dir = Some(dir.to_str().unwrap().to_string());
+        for name in &["a.md", "b.md", "c.md", "d.md"] {
+            files::fwrite(dir.join(::SRC_DIR).join(name), b"title = 1\n%%%\n", true).unwrap();
+        }
+
+        // always the error of the first source, however the work is scheduled
+        for _ in 0..10 {
+            let err = Site::collect(&c).unwrap_err().to_string();
+            assert!(err.contains("a.md"));
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_templates() {
+        let dir = env::temp_dir().join("templates");
+        ::new::create_site(dir.clone(), true).unwrap();
+        let theme = dir.join(::THEME_DIR);
+        files::fwrite(theme.join("page.html"), b"PAGE", true).unwrap();
+        files::fwrite(theme.join("notes").join("post.html"), b"NOTES", true).unwrap();
+        files::fwrite(theme.join("photo.html"), b"PHOTO", true).unwrap();
+
+        let mut c = Conf::default();
+        c.force = Some(true);
+        c.in_dir = Some(dir.to_str().unwrap().to_string());
+        c.out_dir = Some(dir.join("out").to_str().unwrap().to_string());
+
+        let src = dir.join(::SRC_DIR);
+        create_post(src.join("p.md"), c.clone(), PostKind::Page, false).unwrap();
+        create_post(src.join("notes").join("rust").join("n.md"), c.clone(),
+                    PostKind::Article, false).unwrap();
+        create_post(src.join("a.md"), c.clone(), PostKind::Article, false).unwrap();
+        create_post(src.join("b.md"), c.clone(), PostKind::Article, false).unwrap();
+        let b = String::from_utf8(fs::read(src.join("b.md")).unwrap()).unwrap()
+            .replace("kind = ", "template = \"photo.html\"\nkind = ");
+        fs::write(src.join("b.md"), b).unwrap();
+
+        let site = Site::collect(&c).unwrap();
+        let template = |link: &str| {
+            let p = site.articles.iter().chain(site.pages.iter())
+                .find(|p| p.link == link).unwrap();
+            site.post_template(p).unwrap()
+        };
+        assert!(template("p") == "page.html");
+        assert!(template("n") == "notes/post.html");
+        assert!(template("a") == ::POST_FILE);
+        assert!(template("b") == "photo.html");
+        site.generate().unwrap();
+
+        fs::remove_file(theme.join("photo.html")).unwrap();
+        assert!(Site::collect(&c).unwrap().generate().is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_urls() {
+        let dir = env::temp_dir().join("urls");
+        ::new::create_site(dir.clone(), true).unwrap();
+        let c = Conf {
+            force: Some(true),
+            in_dir: Some(dir.to_str().unwrap().to_string()),
+            ..Conf::default()
+        };
+        let src = dir.join(::SRC_DIR);
+        // all on the same day
+        let create = |path: PathBuf, kind| {
+            create_post(&path, c.clone(), kind, false).unwrap();
+            let text = String::from_utf8(fs::read(&path).unwrap()).unwrap()
+                .lines()
+                .map(|l| if l.starts_with("ts = ") { "ts = \"2018-01-02T12:00:00Z\"" } else { l })
+                .collect::<Vec<_>>()
+                .join("\n");
+            fs::write(&path, text + "\n").unwrap();
+        };
+        create(src.join("a.md"), PostKind::Article);
+        Site::collect(&c).unwrap();
+
+        // the URL doesn't depend on the section
+        create(src.join("notes").join("a.md"), PostKind::Article);
+        create(src.join("index.md"), PostKind::Page);
+        let err = Site::collect(&c).unwrap_err().to_string();
+        assert!(err.contains(&format!("{:?}", src.join("a.md"))));
+        assert!(err.contains(&format!("{:?}", src.join("notes").join("a.md"))));
+        assert!(err.contains(&format!("/index.html would be generated from each of {:?}, page 1 of index.html",
+                                      src.join("index.md"))));
+        fs::remove_file(src.join("notes").join("a.md")).unwrap();
+        fs::remove_file(src.join("index.md")).unwrap();
+
+        files::fwrite(dir.join(::STATIC_DIR).join("archive.html"), b"", true).unwrap();
+        assert!(Site::collect(&c).is_err());
+        fs::remove_file(dir.join(::STATIC_DIR).join("archive.html")).unwrap();
+
+        let a = src.join("a.md");
+        let text = String::from_utf8(fs::read(&a).unwrap()).unwrap();
+        for url in &["{{ link }}.html", "/{{ link }}/", "/{{ link }} b.html", "/../{{ link }}.html"] {
+            let meta = text.lines()
+                .map(|l| if l.starts_with("url = ") { format!("url = {:?}", url) } else { l.to_string() })
+                .collect::<Vec<_>>()
+                .join("\n");
+            fs::write(&a, meta).unwrap();
+            assert!(Site::collect(&c).unwrap_err().to_string().contains("invalid URL"));
+        }
+
+        check_url("/2018/01/02/a.html").unwrap();
+        assert!(check_url("/a?b.html").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = env::temp_dir().join("prune-site");
+        ::new::create_site(dir.clone(), true).unwrap();
+        let c = Conf {
+            force: Some(true),
+            in_dir: Some(dir.to_str().unwrap().to_string()),
+            out_dir: Some(dir.to_str().unwrap().to_string()),
+            prune: Some(Default::default()),
+            ..Conf::default()
+        };
+        let src = dir.join(::SRC_DIR);
+        create_post(src.join("p.md"), c.clone(), PostKind::Page, false).unwrap();
+        create_post(src.join("q.md"), c.clone(), PostKind::Page, false).unwrap();
+        files::fwrite(dir.join(::STATIC_DIR).join("x.css"), b"", true).unwrap();
+        // what a repository of a site generated into itself has besides
+        for f in &["README.md", ".gitignore", ".github/workflows/pages.yml", "Makefile",
+                   "scripts/deploy.sh"] {
+            files::fwrite(dir.join(f), b"", true).unwrap();
+        }
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(dir.join("q.html").exists() && dir.join("x.css").exists());
+
+        fs::remove_file(src.join("q.md")).unwrap();
+        // the `prune` section alone doesn't turn it on
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(dir.join("q.html").exists());
+
+        let c = Conf { prune: Some(PruneConf { enabled: Some(true), ..Default::default() }), ..c };
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(!dir.join("q.html").exists());
+        assert!(dir.join("p.html").exists() && dir.join("x.css").exists());
+        assert!(src.join("p.md").exists() && dir.join(::CONFIG_FILE).exists());
+        assert!(dir.join(::THEME_DIR).join(::POST_FILE).exists());
+        assert!(dir.join("README.md").exists() && dir.join(".gitignore").exists());
+        assert!(dir.join(".github").join("workflows").join("pages.yml").exists());
+        assert!(dir.join("Makefile").exists() && dir.join("scripts").join("deploy.sh").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_themes() {
+        let dir = env::temp_dir().join("named-themes");
+        ::new::create_site(dir.clone(), true).unwrap();
+        fs::remove_file(dir.join(::THEME_DIR).join(::INDEX_FILE)).unwrap();
+        fs::remove_file(dir.join(::THEME_DIR).join(::POST_FILE)).unwrap();
+        let write = |path: PathBuf, data: &str| files::fwrite(path, data.as_bytes(), true).unwrap();
+
+        let base = dir.join(::THEMES_DIR).join("base");
+        write(base.join(::TEMPLATES_DIR).join("layout.html"), "BASE {% block body %}{% endblock body %}");
+        write(base.join(::TEMPLATES_DIR).join(::POST_FILE),
+              "{% extends \"layout.html\" %}{% block body %}{{ post.meta.link }}{% endblock body %}");
+        write(base.join(::STATIC_DIR).join("a.css"), "base");
+        write(base.join(::STATIC_DIR).join("b.css"), "base");
+        let child = dir.join(::THEMES_DIR).join("child");
+        write(child.join(::THEME_CONFIG), "extends = \"base\"\n[config]\ntitle = \"Child\"\n");
+        write(child.join(::TEMPLATES_DIR).join("layout.html"), "CHILD {% block body %}{% endblock body %}");
+        write(child.join(::TEMPLATES_DIR).join(::INDEX_FILE), "{{ conf.title }}");
+        write(child.join(::STATIC_DIR).join("a.css"), "child");
+
+        let out = dir.join("out");
+        let c = Conf {
+            force: Some(true),
+            full: Some(true),
+            in_dir: Some(dir.to_str().unwrap().to_string()),
+            out_dir: Some(out.to_str().unwrap().to_string()),
+            theme: Some("child".to_string()),
+            ..Conf::default()
+        };
+        create_post(dir.join(::SRC_DIR).join("p.md"), c.clone(), PostKind::Page, false).unwrap();
+        let read = |name: &str| String::from_utf8(fs::read(out.join(name)).unwrap()).unwrap();
+
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(read("p.html") == "CHILD p");
+        assert!(read(::INDEX_FILE) == "Child");
+        write(child.join(::THEME_CONFIG), "extends = \"base\"\n");
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(read(::INDEX_FILE) == DEFAULT_TITLE);
+        assert!(read("a.css") == "child");
+        assert!(read("b.css") == "base");
+
+        write(dir.join(::THEME_DIR).join(::INDEX_FILE), "SITE");
+        Site::collect(&c).unwrap().generate().unwrap();
+        assert!(read(::INDEX_FILE) == "SITE");
+
+        let c = Conf { theme: Some("none".to_string()), ..c };
+        assert!(Site::collect(&c).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_paginate() {
+        let posts = (0..5).map(|_| Post::new()).collect::<Vec<_>>();
+        let pages = Paginator::paginate(&posts, 2, "/index.html",
+                                        |n| format!("/page/{}/index.html", n));
+
+        assert!(pages.len() == 3);
+        assert!(pages[0].prev == None);
+        assert!(pages[0].next == Some("/page/2/index.html".to_string()));
+        assert!(pages[1].prev == Some("/index.html".to_string()));
+        assert!(pages[2].current == 3 && pages[2].total == 3);
+        assert!(pages[2].items.len() == 1);
+        assert!(pages[2].next == None);
+
+        let pages = Paginator::paginate(&[], 2, "/index.html", |n| n.to_string());
+        assert!(pages.len() == 1 && pages[0].items.is_empty());
+    }
+
+    #[test]
+    fn test_drafts() {
+        let dir = env::temp_dir().join("drafts");
+        ::new::create_site(dir.clone(), true).unwrap();
+
+        let mut c = Conf::default();
+        c.force = Some(true);
+        c.in_dir = Some(dir.to_str().unwrap().to_string());
+        c.drafts = Some(true);
+        create_post(dir.join(::SRC_DIR).join("d.md"), c.clone(), PostKind::Article, true).unwrap();
+
+        assert!(Site::collect(&c).unwrap().articles.len() == 1);
+        c.drafts = None;
+        assert!(Site::collect(&c).unwrap().articles.is_empty());
 
         fs::remove_dir_all(dir).unwrap();
     }
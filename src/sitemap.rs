@@ -0,0 +1,132 @@
+use conf::Conf;
+use error::{Error, Result};
+use feed::{abs_url, escape};
+use post::Post;
+
+const DEFAULT_SITEMAP_PATH: &str = "sitemap.xml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SitemapConf {
+    pub path: Option<String>,
+    pub robots: Option<bool>,
+    pub disallow: Option<Vec<String>>,
+}
+
+impl SitemapConf {
+    pub fn path(&self) -> String {
+        self.path.clone().unwrap_or(DEFAULT_SITEMAP_PATH.to_string())
+    }
+
+    pub fn robots(&self) -> bool {
+        self.robots.unwrap_or(true)
+    }
+}
+
+// A sitemap of the home page and all posts that don't opt out,
+// which are assumed to be sorted from the newest to the oldest.
+pub struct Sitemap<'a> {
+    base_url: &'a str,
+    conf: SitemapConf,
+    posts: Vec<&'a Post>,
+}
+
+impl<'a> Sitemap<'a> {
+    pub fn new<I>(conf: &'a Conf, posts: I) -> Result<Self>
+            where I: IntoIterator<Item = &'a Post>
+    {
+        let base_url = conf.base_url.as_ref()
+            .ok_or(Error::new("base_url is required to generate the sitemap".to_string()))?;
+
+        Ok(Sitemap {
+            base_url,
+            conf: conf.sitemap.clone().unwrap_or_default(),
+            // drafts are never meant to be found
+            posts: posts.into_iter()
+                .filter(|p| !p.draft && p.sitemap.unwrap_or(true))
+                .collect(),
+        })
+    }
+
+    pub fn path(&self) -> String {
+        self.conf.path()
+    }
+
+    pub fn xml(&self) -> Result<String> {
+        let mut xml = String::new();
+        xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+        xml += "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n";
+
+        let latest = self.posts.iter().map(|p| p.lastmod()).max();
+        xml += "  <url>\n";
+        xml += &format!("    <loc>{}</loc>\n", escape(&abs_url(self.base_url, "/")));
+        if let Some(t) = latest {
+            xml += &format!("    <lastmod>{}</lastmod>\n", t.to_rfc3339());
+        }
+        xml += "  </url>\n";
+
+        for p in &self.posts {
+            xml += "  <url>\n";
+            xml += &format!("    <loc>{}</loc>\n", escape(&abs_url(self.base_url, &p.url()?)));
+            xml += &format!("    <lastmod>{}</lastmod>\n", p.lastmod().to_rfc3339());
+            xml += "  </url>\n";
+        }
+
+        xml += "</urlset>\n";
+        Ok(xml)
+    }
+
+    // `None` if robots.txt is turned off.
+    pub fn robots(&self) -> Option<String> {
+        if !self.conf.robots() {
+            return None;
+        }
+
+        let mut txt = "User-agent: *\n".to_string();
+        match self.conf.disallow {
+            Some(ref paths) if !paths.is_empty() => {
+                for p in paths {
+                    txt += &format!("Disallow: {}\n", p);
+                }
+            },
+            _ => txt += "Disallow:\n",
+        }
+        txt += &format!("\nSitemap: {}\n", abs_url(self.base_url, &self.conf.path()));
+        Some(txt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sitemap() {
+        let mut c = Conf::default();
+        c.base_url = Some("https://example.com".to_string());
+        c.sitemap = Some(SitemapConf { disallow: Some(vec!["/tmp/".to_string()]),
+                                       ..SitemapConf::default() });
+
+        let mut p = Post::new();
+        p.meta.link = "a&b".to_string();
+        p.meta.url = "/{{ link }}.html".to_string();
+        p.meta.ts = "2000-01-15T12:00:00Z".parse().unwrap();
+        p.meta.updated = Some("2000-02-15T12:00:00Z".parse().unwrap());
+        let mut hidden = Post::new();
+        hidden.meta.sitemap = Some(false);
+        let posts = vec![p, hidden];
+
+        let sitemap = Sitemap::new(&c, &posts).unwrap();
+        let xml = sitemap.xml().unwrap();
+        assert!(xml.contains("<loc>https://example.com/</loc>"));
+        assert!(xml.contains("<loc>https://example.com/a&amp;b.html</loc>"));
+        assert!(xml.matches("<lastmod>2000-02-15T").count() == 2);
+        assert!(xml.matches("<url>").count() == 2);
+
+        let robots = sitemap.robots().unwrap();
+        assert!(robots.contains("Disallow: /tmp/\n"));
+        assert!(robots.contains("Sitemap: https://example.com/sitemap.xml\n"));
+
+        c.base_url = None;
+        assert!(Sitemap::new(&c, &posts).is_err());
+    }
+}
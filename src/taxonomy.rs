@@ -0,0 +1,97 @@
+use post::Post;
+use slug::slugify;
+use std::collections::BTreeMap;
+
+#[derive(Debug)]
+pub struct Taxonomy {
+    pub name: String,
+    // term slug -> (term name, indices of articles with that term)
+    terms: BTreeMap<String, (String, Vec<usize>)>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Term<'a> {
+    pub name: &'a str,
+    pub slug: &'a str,
+    pub url: String,
+    pub articles: Vec<&'a Post>,
+}
+
+impl Taxonomy {
+    pub fn new<F>(name: &str, articles: &[Post], terms_of: F) -> Self
+            where F: Fn(&Post) -> &Vec<String>
+    {
+        let mut terms = BTreeMap::new();
+        for (i, p) in articles.iter().enumerate() {
+            for t in terms_of(p) {
+                // terms differing only in case or punctuation share one page,
+                // the first spelling seen wins
+                terms.entry(slugify(t))
+                     .or_insert_with(|| (t.to_string(), vec![]))
+                     .1.push(i);
+            }
+        }
+        Taxonomy { name: name.to_string(), terms }
+    }
+
+    pub fn from_articles(articles: &[Post]) -> Vec<Self> {
+        vec![
+            Taxonomy::new("tags", articles, |p| &p.tags),
+            Taxonomy::new("categories", articles, |p| &p.categories),
+        ]
+    }
+
+    pub fn url(&self) -> String {
+        format!("/{}/{}", self.name, ::INDEX_FILE)
+    }
+
+    pub fn term_url(&self, slug: &str) -> String {
+        format!("/{}/{}.html", self.name, slug)
+    }
+
+    // Indices of the articles filed under each term of the `i`-th article,
+    // once for each term, including `i` itself.
+    pub fn sharing(&self, i: usize) -> Vec<usize> {
+        self.terms.values()
+            .filter(|(_, indices)| indices.contains(&i))
+            .flat_map(|(_, indices)| indices.iter().cloned())
+            .collect()
+    }
+
+    pub fn terms<'a>(&'a self, articles: &'a [Post]) -> Vec<Term<'a>> {
+        self.terms.iter()
+            .map(|(slug, (name, indices))| Term {
+                name,
+                slug,
+                url: self.term_url(slug),
+                articles: indices.iter().map(|&i| &articles[i]).collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(tags: &[&str]) -> Post {
+        let mut p = Post::new();
+        p.meta.tags = tags.iter().map(|t| t.to_string()).collect();
+        p
+    }
+
+    #[test]
+    fn test_taxonomy_terms() {
+        let articles = vec![tagged(&["Rust", "web"]), tagged(&["rust"]), tagged(&[])];
+        let tax = Taxonomy::new("tags", &articles, |p| &p.tags);
+        let terms = tax.terms(&articles);
+
+        assert!(terms.len() == 2);
+        assert!(terms[0].name == "Rust");
+        assert!(terms[0].url == "/tags/rust.html");
+        assert!(terms[0].articles.len() == 2);
+        assert!(terms[1].slug == "web");
+        assert!(terms[1].articles.len() == 1);
+        assert!(tax.url() == "/tags/index.html");
+    }
+}
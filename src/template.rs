@@ -0,0 +1,257 @@
+use chrono::{DateTime, Local};
+use conf::Conf;
+use feed;
+use markdown;
+use post;
+use serde::Deserialize;
+use slug;
+use std::collections::{BTreeMap, HashMap};
+use tera::{self, GlobalFn, Tera, Value};
+
+const WORDS_PER_MINUTE: usize = 200;
+const DEFAULT_TRUNCATE_LENGTH: u64 = 255;
+const DEFAULT_TRUNCATE_END: &str = "…";
+// elements without a closing tag
+const VOID_ELEMENTS: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input",
+                                 "link", "meta", "source", "track", "wbr"];
+
+fn arg<'a>(args: &'a HashMap<String, Value>, name: &str, func: &str) -> tera::Result<&'a Value> {
+    args.get(name).ok_or_else(|| format!("`{}` requires the `{}` argument", func, name).into())
+}
+
+fn string<'a>(value: &'a Value, func: &str) -> tera::Result<&'a str> {
+    value.as_str().ok_or_else(|| format!("`{}` expects a string, got {}", func, value).into())
+}
+
+// The metadata of a post its URL is made of.
+#[derive(Deserialize)]
+struct UrlMeta {
+    link: String,
+    url: String,
+    ts: DateTime<Local>,
+}
+
+// Just what `url_of` needs of a post, to leave its content alone.
+#[derive(Deserialize)]
+struct UrlPost {
+    meta: UrlMeta,
+    section: String,
+}
+
+fn url_of(post: &Value, func: &str) -> tera::Result<String> {
+    let UrlPost { meta, section } = UrlPost::deserialize(post)
+        .map_err(|e| format!("`{}` expects a post: {}", func, e))?;
+    post::url(&meta.url, &meta.ts, &meta.link, &section).map_err(|e| e.to_string().into())
+}
+
+// The text of `html` without any tags.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+    text
+}
+
+fn words(html: &str) -> usize {
+    strip_tags(html).split_whitespace().count()
+}
+
+// Length in bytes of the character or entity at the start of `s`.
+fn char_len(s: &str) -> usize {
+    if s.starts_with('&') {
+        if let Some(i) = s.find(';') {
+            if s[1..i].chars().all(|c| c.is_alphanumeric() || c == '#') {
+                return i + 1;
+            }
+        }
+    }
+    s.chars().next().map(|c| c.len_utf8()).unwrap_or(0)
+}
+
+// `html` cut after `length` characters of text followed by `end`,
+// with the elements left open closed.
+fn truncate(html: &str, length: usize, end: &str) -> String {
+    let mut out = String::new();
+    let mut open: Vec<String> = vec![];
+    let mut count = 0;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let len = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+            let tag = &rest[..len];
+            let name = tag.trim_start_matches(['<', '/'])
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or("")
+                .to_lowercase();
+            if tag.starts_with("</") {
+                if let Some(i) = open.iter().rposition(|t| *t == name) {
+                    open.truncate(i);
+                }
+            }
+            else if !tag.starts_with("<!") && !tag.ends_with("/>")
+                    && !VOID_ELEMENTS.contains(&name.as_str()) {
+                open.push(name);
+            }
+            out += tag;
+            rest = &rest[len..];
+            continue;
+        }
+
+        if count == length {
+            if strip_tags(rest).trim().is_empty() {
+                return html.to_string();
+            }
+            out += end;
+            for t in open.iter().rev() {
+                out += &format!("</{}>", t);
+            }
+            return out;
+        }
+        let len = char_len(rest);
+        out += &rest[..len];
+        rest = &rest[len..];
+        count += 1;
+    }
+    out
+}
+
+// Markdown rendered with the default options, since a filter
+// can't know about the configuration of the site.
+pub fn markdown(value: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
+    markdown::markdown_to_html(string(&value, "markdown")?, &Conf::default(), None)
+        .map(Value::String)
+        .map_err(|e| e.to_string().into())
+}
+
+// The same as the slugs of terms.
+pub fn slugify(value: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
+    Ok(Value::String(slug::slugify(string(&value, "slugify")?)))
+}
+
+pub fn word_count(value: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
+    Ok(Value::from(words(string(&value, "word_count")?)))
+}
+
+// In minutes, rounded up.
+pub fn reading_time(value: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
+    let n = words(string(&value, "reading_time")?);
+    Ok(Value::from(n.div_ceil(WORDS_PER_MINUTE)))
+}
+
+pub fn truncate_html(value: Value, args: HashMap<String, Value>) -> tera::Result<Value> {
+    let length = args.get("length").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_TRUNCATE_LENGTH);
+    let end = args.get("end").and_then(|v| v.as_str()).unwrap_or(DEFAULT_TRUNCATE_END);
+    Ok(Value::String(truncate(string(&value, "truncate_html")?, length as usize, end)))
+}
+
+pub fn url_for() -> GlobalFn {
+    Box::new(|args| url_of(arg(&args, "post", "url_for")?, "url_for").map(Value::String))
+}
+
+// Either of a `path` or a `post`.
+pub fn abs_url(base_url: Option<String>) -> GlobalFn {
+    Box::new(move |args| {
+        let base_url = base_url.as_ref()
+            .ok_or_else(|| tera::Error::from("base_url is required by `abs_url`".to_string()))?;
+        let path = match args.get("post") {
+            Some(post) => url_of(post, "abs_url")?,
+            None => string(arg(&args, "path", "abs_url")?, "abs_url")?.to_string(),
+        };
+        Ok(Value::String(feed::abs_url(base_url, &path)))
+    })
+}
+
+// `posts` by the paths of their sources relative to the source directory.
+pub fn get_page(posts: BTreeMap<String, Value>) -> GlobalFn {
+    Box::new(move |args| {
+        let path = string(arg(&args, "path", "get_page")?, "get_page")?;
+        posts.get(path).cloned()
+            .ok_or_else(|| format!("`get_page` found no post at {:?}", path).into())
+    })
+}
+
+// The terms of each taxonomy by its name.
+pub fn get_taxonomy(taxonomies: BTreeMap<String, Value>) -> GlobalFn {
+    Box::new(move |args| {
+        let kind = string(arg(&args, "kind", "get_taxonomy")?, "get_taxonomy")?;
+        taxonomies.get(kind).cloned()
+            .ok_or_else(|| format!("`get_taxonomy` found no taxonomy {:?}", kind).into())
+    })
+}
+
+pub fn register(tera: &mut Tera, conf: &Conf, posts: BTreeMap<String, Value>,
+                taxonomies: BTreeMap<String, Value>) {
+    tera.register_filter("markdown", markdown);
+    tera.register_filter("slugify", slugify);
+    tera.register_filter("word_count", word_count);
+    tera.register_filter("reading_time", reading_time);
+    tera.register_filter("truncate_html", truncate_html);
+    tera.register_global_function("url_for", url_for());
+    tera.register_global_function("abs_url", abs_url(conf.base_url.clone()));
+    tera.register_global_function("get_page", get_page(posts));
+    tera.register_global_function("get_taxonomy", get_taxonomy(taxonomies));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::post::Post;
+
+    #[test]
+    fn test_truncate() {
+        let html = "<p>Hello <em>wide</em> world</p>\n<p>Again</p>\n";
+        assert!(truncate(html, 8, "…") == "<p>Hello <em>wi…</em></p>");
+        assert!(truncate(html, 100, "…") == html);
+        assert!(truncate("<p>a&amp;b<br>c</p>", 3, "") == "<p>a&amp;b<br></p>");
+        // nothing but tags and spaces is left
+        assert!(truncate("<p>ab</p>\n", 2, "…") == "<p>ab</p>\n");
+    }
+
+    #[test]
+    fn test_filters() {
+        let v = |s: &str| Value::String(s.to_string());
+        let html = "<p>one two</p> three";
+        assert!(word_count(v(html), HashMap::new()).unwrap() == 3);
+        assert!(reading_time(v(html), HashMap::new()).unwrap() == 1);
+        assert!(reading_time(v(&"w ".repeat(401)), HashMap::new()).unwrap() == 3);
+        assert!(slugify(v("Hello World"), HashMap::new()).unwrap() == "hello-world");
+        assert!(markdown(v("*a*"), HashMap::new()).unwrap() == "<p><em>a</em></p>\n");
+        assert!(markdown(Value::from(1), HashMap::new()).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), Value::from(3));
+        assert!(truncate_html(v(html), args).unwrap() == "<p>one…</p>");
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut p = Post::new();
+        p.meta.link = "a".to_string();
+        p.meta.url = "/{{ section }}/{{ link }}.html".to_string();
+        p.section = "notes".to_string();
+        let mut args = HashMap::new();
+        args.insert("post".to_string(), tera::to_value(&p).unwrap());
+
+        assert!(url_for()(args.clone()).unwrap() == "/notes/a.html");
+        assert!(abs_url(Some("https://example.com/".to_string()))(args.clone()).unwrap()
+                == "https://example.com/notes/a.html");
+        assert!(abs_url(None)(args.clone()).is_err());
+        assert!(url_for()(HashMap::new()).is_err());
+
+        let mut posts = BTreeMap::new();
+        posts.insert("notes/a.md".to_string(), tera::to_value(&p).unwrap());
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), Value::from("notes/a.md"));
+        assert!(get_page(posts.clone())(args.clone()).unwrap()["meta"]["link"] == "a");
+        args.insert("path".to_string(), Value::from("b.md"));
+        assert!(get_page(posts)(args).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use conf::Conf;
+use error::{Error, Result};
+use files;
+use std::path::{Path, PathBuf};
+use toml::{self, Value};
+
+// options a theme may not set, as they control what a build does with the files
+// of the user rather than how the site looks
+const SITE_ONLY: &[&str] = &["force", "drafts", "full", "in_dir", "out_dir", "port",
+                             "prune", "theme"];
+
+#[derive(Deserialize, Debug, Default)]
+struct ThemeFile {
+    // name of the parent theme
+    extends: Option<String>,
+    // configuration used where the site doesn't set it
+    config: Option<Value>,
+}
+
+// A theme under `themes/<name>/`, with its templates, static files
+// and `theme.toml`, all of which are optional.
+#[derive(Debug)]
+pub struct Theme {
+    pub name: String,
+    pub dir: PathBuf,
+    extends: Option<String>,
+    config: Option<Value>,
+}
+
+impl Theme {
+    pub fn load<P: AsRef<Path>>(in_dir: P, name: &str) -> Result<Self> {
+        let dir = in_dir.as_ref().join(::THEMES_DIR).join(name);
+        if !dir.is_dir() {
+            return Err(Error::new(format!("theme {:?} not found at {:?}", name, dir)));
+        }
+
+        let path = dir.join(::THEME_CONFIG);
+        let file: ThemeFile = if path.exists() {
+            toml::from_slice(&files::fread(&path)?)
+                .map_err(|e| Error::new(format!("error parsing {:?}: {}", path, e)))?
+        }
+        else {
+            ThemeFile::default()
+        };
+
+        Ok(Theme {
+            name: name.to_string(),
+            dir,
+            extends: file.extends,
+            config: file.config,
+        })
+    }
+
+    pub fn templates_dir(&self) -> PathBuf {
+        self.dir.join(::TEMPLATES_DIR)
+    }
+
+    pub fn static_dir(&self) -> PathBuf {
+        self.dir.join(::STATIC_DIR)
+    }
+}
+
+// The theme named `name` followed by its parent, the parent of that, etc.
+pub fn chain<P: AsRef<Path>>(in_dir: P, name: Option<&str>) -> Result<Vec<Theme>> {
+    let mut themes: Vec<Theme> = vec![];
+    let mut next = name.map(String::from);
+    while let Some(name) = next {
+        if themes.iter().any(|t| t.name == name) {
+            return Err(Error::new(format!("theme {:?} ends up extending itself", name)));
+        }
+        let theme = Theme::load(&in_dir, &name)?;
+        next = theme.extends.clone();
+        themes.push(theme);
+    }
+    Ok(themes)
+}
+
+// Tables are merged key by key, anything else in `over` replaces `base`.
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (&mut Value::Table(ref mut b), Value::Table(o)) => {
+            for (k, v) in o {
+                match b.get_mut(&k) {
+                    Some(old) => merge(old, v),
+                    None => {
+                        b.insert(k, v);
+                    },
+                }
+            }
+        },
+        (b, o) => *b = o,
+    }
+}
+
+// The configuration of the site on top of that of `themes`,
+// each of which is on top of the themes it extends, leaving
+// out what themes may not set.
+pub fn apply(conf: &Conf, themes: &[Theme]) -> Result<Conf> {
+    if themes.is_empty() {
+        return Ok(conf.clone());
+    }
+
+    let mut value = Value::Table(Default::default());
+    for t in themes.iter().rev() {
+        if let Some(mut c) = t.config.clone() {
+            if let Value::Table(ref mut table) = c {
+                for k in SITE_ONLY {
+                    table.remove(*k);
+                }
+            }
+            merge(&mut value, c);
+        }
+    }
+    merge(&mut value, Value::try_from(conf)
+          .map_err(|e| Error::new(format!("error applying theme configuration: {}", e)))?);
+    value.try_into()
+        .map_err(|e| Error::new(format!("error in theme configuration: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::feed::FeedConf;
+    use ::std::{env, fs};
+
+    #[test]
+    fn test_chain() {
+        let dir = env::temp_dir().join("themes");
+        let write = |name: &str, data: &str| {
+            let path = dir.join(::THEMES_DIR).join(name).join(::THEME_CONFIG);
+            files::fwrite(path, data.as_bytes(), true).unwrap()
+        };
+        write("base", "[config]\ntitle = \"Base\"\npaginate = 5\nforce = true\n\
+                       out_dir = \"/tmp\"\n\
+                       [config.feed]\nlimit = 3\npath = \"base.xml\"\n\
+                       [config.prune]\nenabled = true\n");
+        write("dark", "extends = \"base\"\n[config]\npaginate = 10\n");
+
+        let themes = chain(&dir, Some("dark")).unwrap();
+        assert!(themes.iter().map(|t| t.name.as_str()).collect::<Vec<_>>() == vec!["dark", "base"]);
+        assert!(chain(&dir, None).unwrap().is_empty());
+        assert!(chain(&dir, Some("light")).is_err());
+
+        let c = Conf {
+            title: Some("Mine".to_string()),
+            feed: Some(FeedConf { limit: Some(7), ..Default::default() }),
+            ..Conf::default()
+        };
+        let c = apply(&c, &themes).unwrap();
+        assert!(c.title == Some("Mine".to_string()));
+        assert!(c.paginate == Some(10));
+        let feed = c.feed.unwrap();
+        assert!(feed.limit() == 7);
+        assert!(feed.path() == "base.xml");
+        // a theme doesn't get to say what is done with the files of the site
+        assert!(c.force.is_none() && c.out_dir.is_none() && c.prune.is_none());
+
+        write("base", "extends = \"dark\"\n");
+        assert!(chain(&dir, Some("dark")).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
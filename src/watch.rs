@@ -0,0 +1,138 @@
+use error::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL_MS: u64 = 500;
+
+fn walk(path: &Path, mtimes: &mut BTreeMap<PathBuf, SystemTime>) {
+    let meta = match fs::metadata(path) {
+        Ok(m) => m,
+        Err(_) => return,
+    };
+    if meta.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                walk(&entry.path(), mtimes);
+            }
+        }
+    }
+    else if let Ok(t) = meta.modified() {
+        mtimes.insert(path.to_path_buf(), t);
+    }
+}
+
+// Modification times of all files under the given paths, which
+// can be either files or directories.
+pub fn snapshot(paths: &[PathBuf]) -> BTreeMap<PathBuf, SystemTime> {
+    let mut mtimes = BTreeMap::new();
+    for p in paths {
+        walk(p, &mut mtimes);
+    }
+    mtimes
+}
+
+// Poll the given paths forever, calling `f` whenever some file
+// is created, removed or modified.
+pub fn on_change<F: FnMut()>(paths: Vec<PathBuf>, mut f: F) {
+    let mut last = snapshot(&paths);
+    loop {
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        let now = snapshot(&paths);
+        if now != last {
+            last = now;
+            f();
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Build {
+    pub version: u64,
+    pub error: Option<String>,
+}
+
+// Result of the latest build, shared between the thread rebuilding
+// the site and the server threads waiting to notify the browser.
+#[derive(Debug)]
+pub struct Reload {
+    build: Mutex<Build>,
+    cond: Condvar,
+}
+
+impl Default for Reload {
+    fn default() -> Self {
+        Reload {
+            build: Mutex::new(Build { version: 0, error: None }),
+            cond: Condvar::new(),
+        }
+    }
+}
+
+impl Reload {
+    pub fn new() -> Self {
+        Reload::default()
+    }
+
+    pub fn current(&self) -> Build {
+        self.build.lock().unwrap().clone()
+    }
+
+    pub fn done(&self, result: Result<()>) {
+        let mut build = self.build.lock().unwrap();
+        build.version += 1;
+        build.error = result.err().map(|e| e.to_string());
+        self.cond.notify_all();
+    }
+
+    // Wait until a build newer than `version` finishes, or the timeout expires.
+    pub fn wait(&self, version: u64, timeout: Duration) -> Build {
+        let build = self.build.lock().unwrap();
+        let (build, _) = self.cond
+            .wait_timeout_while(build, timeout, |b| b.version == version)
+            .unwrap();
+        build.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::error::Error;
+    use ::std::{env, fs};
+    use ::std::sync::Arc;
+
+    #[test]
+    fn test_snapshot() {
+        let dir = env::temp_dir().join("watch");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("a"), b"a").unwrap();
+
+        let paths = vec![dir.clone(), dir.join("missing")];
+        let before = snapshot(&paths);
+        assert!(before.len() == 1);
+
+        fs::write(dir.join("b"), b"b").unwrap();
+        assert!(snapshot(&paths) != before);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload() {
+        let reload = Arc::new(Reload::new());
+        let r = reload.clone();
+        let t = thread::spawn(move || r.done(Err(Error::new("oops".to_string()))));
+
+        let build = reload.wait(0, Duration::from_secs(10));
+        assert!(build.version == 1);
+        assert!(build.error == Some("oops".to_string()));
+        t.join().unwrap();
+
+        // nothing newer, time out with the current build
+        assert!(reload.wait(1, Duration::from_millis(10)) == build);
+    }
+}